@@ -1,87 +1,335 @@
-use std::{fs, env, sync::Arc};
-use log4rs::{append::console::ConsoleAppender, config::{Root, Appender}, encode::pattern::PatternEncoder};
-use soulfire::{Game, RoleConnectionMetadataRecord};
+use std::{collections::BTreeMap, fs, future::Future, path::PathBuf, process::exit};
+use clap::{Parser, Subcommand, ValueEnum};
+use log4rs::{append::console::{ConsoleAppender, Target}, config::{Root, Appender}, encode::pattern::PatternEncoder};
+use soulfire::{configure::{application_fields, check_application, diff, skeleton, skeleton_yaml, Change, GameReport, Outcome, Record, Report}, discord::{ApplicationCommand, DiscordApi, DiscordConfig, DiscordError, ReqwestDiscordApi}, locales::Locales, registry::GameRegistry, settings::Settings, Game};
+
+/// Keeps each game's Discord application in line with its config.
+///
+/// Exits with 1 if anything failed.
+#[derive(Parser)]
+struct Cli {
+    /// How to print the report. Logs go to stderr with `json`
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    #[command(subcommand)]
+    command: Command
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check the game configs, commands and locales without talking to Discord
+    Validate,
+    /// Print what `push` would change, without changing anything
+    Diff {
+        /// Only look at these games
+        #[arg(long)]
+        game: Vec<String>
+    },
+    /// Update each game's application to match its config
+    Push {
+        /// Only update these games
+        #[arg(long)]
+        game: Vec<String>
+    },
+    /// Write a game config with the metadata its application has now
+    Pull {
+        /// The id of the game, which its config is named after
+        game: String,
+        /// The suffix of its credentials, like APP_ID_<SUFFIX> [default: the id in upper case]
+        #[arg(long)]
+        suffix: Option<String>,
+        /// Where to write it [default: <games dir>/<game>.yml]
+        #[arg(long)]
+        output: Option<PathBuf>
+    }
+}
+
+/// Everything that's synced to Discord, once it's been checked.
+struct Config {
+    games: BTreeMap<String, Game>,
+    commands: Vec<ApplicationCommand>
+}
+
+/// Loads and checks every game, the commands and the locales, returning
+/// every problem found. No secrets are needed for this.
+fn load_config(settings: &Settings) -> Result<Config, Vec<String>> {
+    let mut problems = Vec::new();
+
+    let games = GameRegistry::load_configs(settings.games_dir.clone().unwrap_or_else(|| "games".into()))
+        .map_err(|report| problems.extend(report.0.iter().map(ToString::to_string)))
+        .ok();
+
+    let commands_file = settings.commands_file.clone().unwrap_or_else(|| "commands.json".into());
+    let commands = fs::read_to_string(&commands_file)
+        .map_err(|e| format!("failed to read {commands_file:?}: {e}"))
+        .and_then(|commands| serde_json::from_str::<Vec<ApplicationCommand>>(&commands).map_err(|e| format!("failed to parse {commands_file:?}: {e}")))
+        .map_err(|e| problems.push(e))
+        .ok();
+    let locales = Locales::from_dir(settings.locales_dir.clone().unwrap_or_else(|| "locales".into()))
+        .map_err(|e| problems.push(format!("failed to load locales: {e}")))
+        .ok();
+
+    match (games, commands, locales) {
+        (Some(games), Some(mut commands), Some(locales)) if problems.is_empty() => {
+            locales.localize_commands(&mut commands);
+            commands.sort();
+
+            Ok(Config { games, commands })
+        },
+        _ => Err(problems)
+    }
+}
+
+/// The secrets configuring a game's application takes, which are a subset of
+/// what serving it does.
+struct AppSecrets {
+    bot_token: String,
+    application_id: u64,
+    client_id: u64
+}
+
+/// Reads `BOT_TOKEN_<SUFFIX>`, `APP_ID_<SUFFIX>` and `CLIENT_ID_<SUFFIX>`,
+/// reporting every one that is missing or malformed.
+fn app_secrets(settings: &Settings, suffix: &str) -> Result<AppSecrets, Vec<String>> {
+    match (
+        settings.require_secret(suffix, "BOT_TOKEN", |s| s.bot_token.as_ref()),
+        settings.require_id(suffix, "APP_ID", |s| s.app_id.as_ref()),
+        settings.require_id(suffix, "CLIENT_ID", |s| s.client_id.as_ref())
+    ) {
+        (Ok(bot_token), Ok(application_id), Ok(client_id)) => Ok(AppSecrets { bot_token, application_id, client_id }),
+        (bot_token, application_id, client_id) => Err([bot_token.err(), application_id.err(), client_id.err()].into_iter().flatten().map(|e| e.to_string()).collect())
+    }
+}
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+
     log4rs::init_config(log4rs::Config::builder()
         .appender(Appender::builder()
             .build("console", Box::new(ConsoleAppender::builder()
                 .encoder(Box::new(PatternEncoder::new("{h([{l}])} {t} -> {m}{n}")))
+                .target(if cli.format == Format::Json { Target::Stderr } else { Target::Stdout })
                 .build())))
         .build(Root::builder().appender("console").build(log::LevelFilter::Info))
         .unwrap()).unwrap();
-    
-    log::info!(target: "soulfire::configure", "Updating config for all specified games");
-    let client = Arc::new(reqwest::Client::default());
-    
-    for game in fs::read_dir("games").unwrap().filter_map(Result::ok) {
-        if game.file_type().unwrap().is_file() {
-            let contents = fs::read_to_string(game.path()).unwrap();
-            let yaml: Game = serde_yml::from_str(&contents).unwrap_or_else(|e| panic!("failed to parse game {:?}: {e}", game.path()));
-            let log_target = format!("soulfire::configure[{}]", game.path().with_extension("").file_name().unwrap_or_default().to_string_lossy());
-            log::info!(target: &log_target, "Starting update for {}", &yaml.name);
-            
-            let bot_token = env::var(format!("BOT_TOKEN_{}", yaml.suffix)).expect("no bot token for a game!");
-            let application_id = env::var(format!("APP_ID_{}", yaml.suffix)).expect("no app id for a game!");
-            
-            let name_1 = yaml.name.clone();
-            let name_2 = yaml.name.clone();
-            let name = yaml.name.clone();
-            
-            let client_1 = client.clone();
-            let application_id_1 = application_id.clone();
-            let bot_token_1 = bot_token.clone();
-            let (existing_data, new_data) = tokio::join!(
-                tokio::spawn(async move {
-                    let client = client_1;
-                    let existing_data = client
-                        .get(format!("https://discord.com/api/v10/applications/{application_id_1}/role-connections/metadata"))
-                        .header("Authorization", format!("Bot {bot_token_1}"))
-                        .header("User-Agent", "DiscordBot (https://github.com/der-fruhling)")
-                        .send().await.expect("failed to send request")
-                        .error_for_status().expect("request rejected")
-                        .text().await.expect("failed to read text of existing data");
-                    
-                    let mut existing_data: Vec<RoleConnectionMetadataRecord> = serde_json::from_str(&existing_data).expect("failed to parse existing data");
-                    
-                    existing_data.sort();
-                    log::debug!("Existing data for {}: {:?}", name_1, existing_data);
-                    existing_data
-                }),
-                tokio::spawn(async move {
-                    let mut records = yaml.make_role_connection_records();
-                    records.sort();
-                    log::debug!("New data for {}: {:?}", name_2, records);
-                    records
-                })
-            );
-            
-            let (existing_data, new_data) = (existing_data.unwrap(), new_data.unwrap());
-            
-            if existing_data != new_data {
-                log::warn!(target: &log_target, "Existing and new data for {} do not match! Updating...", &name);
-                let client = client.clone();
-                let json = serde_json::to_string(&new_data).unwrap();
-                log::debug!("Sending {:?} as new role connection metadata for {}", &json, &name);
-                let res = client
-                    .put(format!("https://discord.com/api/v10/applications/{application_id}/role-connections/metadata"))
-                    .body(json)
-                    .header("Authorization", format!("Bot {bot_token}"))
-                    .header("User-Agent", "DiscordBot (https://github.com/der-fruhling)")
-                    .header("Content-Type", "application/json")
-                    .send().await.expect("failed to send request");
-                
-                if !res.status().is_success() {
-                    let status = res.status();
-                    let error = res.text().await.expect("failed to read error text");
-                    log::error!(target: &log_target, "{} {} (put): {}", status, &name, error);
-                } else {
-                    log::info!(target: &log_target, "Updated data for {}", name);
+
+    let report = match run(cli.command).await {
+        Ok(games) => Report { games, problems: Vec::new() },
+        Err(problems) => Report { games: Vec::new(), problems }
+    };
+
+    match cli.format {
+        Format::Text => print_report(&report),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&report).unwrap())
+    }
+
+    if report.failed() {
+        exit(1);
+    }
+}
+
+fn print_report(report: &Report) {
+    for game in &report.games {
+        for (what, changes) in [
+            ("application", game.application.iter().map(ToString::to_string).collect::<Vec<_>>()),
+            ("metadata", game.metadata.iter().map(ToString::to_string).collect()),
+            ("commands", game.commands.iter().map(ToString::to_string).collect())
+        ] {
+            if !changes.is_empty() {
+                println!("{} {what}:", game.game);
+
+                for change in changes {
+                    println!("  {change}");
                 }
-            } else {
-                log::info!(target: &log_target, "Game {} up to date", name);
             }
         }
     }
+
+    if !report.problems.is_empty() {
+        eprintln!("found {} problem(s):", report.problems.len());
+
+        for problem in &report.problems {
+            eprintln!("  - {problem}");
+        }
+    }
+}
+
+/// Runs `command`, returning a report for each game it looked at, or the
+/// problems that kept it from running at all.
+async fn run(command: Command) -> Result<Vec<GameReport>, Vec<String>> {
+    let settings = Settings::load().map_err(|e| vec![format!("invalid settings: {e}")])?;
+    let discord_config = DiscordConfig::from_settings(&settings).map_err(|e| vec![e.to_string()])?;
+    let discord = ReqwestDiscordApi::new(reqwest::Client::default(), &discord_config);
+
+    match command {
+        Command::Validate => {
+            let config = load_config(&settings)?;
+            log::info!(target: "soulfire::configure", "{} game(s) and {} command(s) are valid", config.games.len(), config.commands.len());
+            Ok(Vec::new())
+        },
+        Command::Diff { game } => sync_all(&settings, &discord, &game, false).await,
+        Command::Push { game } => sync_all(&settings, &discord, &game, true).await,
+        Command::Pull { game, suffix, output } => pull(&settings, &discord, &game, suffix, output).await.map(|()| Vec::new())
+    }
+}
+
+/// Diffs, and if `push` is set updates, every game in `filter`, or every game
+/// if it's empty.
+async fn sync_all(settings: &Settings, discord: &impl DiscordApi, filter: &[String], push: bool) -> Result<Vec<GameReport>, Vec<String>> {
+    let config = load_config(settings)?;
+    let mut ids = Vec::new();
+    for id in filter {
+        let canonical = config.games.get_key_value(id)
+            .or_else(|| config.games.iter().find(|(_, game)| game.aliases.contains(id)))
+            .map(|(id, _)| id.as_str());
+        ids.push(canonical.ok_or_else(|| vec![format!("there is no game {id:?}")])?);
+    }
+
+    let mut selected = Vec::new();
+    let mut problems = Vec::new();
+    for (id, game) in config.games.iter().filter(|(id, _)| ids.is_empty() || ids.contains(&id.as_str())) {
+        match app_secrets(settings, &game.suffix) {
+            Ok(secrets) => selected.push((id, game, secrets)),
+            Err(e) => problems.extend(e)
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+
+    let mut reports = Vec::new();
+    for (id, game, secrets) in selected {
+        reports.push(sync(discord, settings.domain(), id, game, &secrets, &config.commands, push).await);
+    }
+
+    Ok(reports)
+}
+
+async fn sync(discord: &impl DiscordApi, domain: &str, id: &str, game: &Game, secrets: &AppSecrets, commands: &[ApplicationCommand], push: bool) -> GameReport {
+    let log_target = format!("soulfire::configure[{id}]");
+    let (application_id, bot_token) = (secrets.application_id, secrets.bot_token.as_str());
+    let name = &game.name;
+    let mut report = GameReport::new(id);
+    log::info!(target: &log_target, "Starting {} for {}", if push { "update" } else { "diff" }, name);
+
+    let application = match discord.get_current_application(bot_token).await {
+        Ok(application) => application,
+        Err(e) => {
+            failed(&log_target, name, "get application", e, &mut report.errors);
+            report.outcome = Outcome::Failed;
+            return report;
+        }
+    };
+
+    let mismatches = check_application(&application, &game.suffix, application_id, secrets.client_id);
+    if !mismatches.is_empty() {
+        for mismatch in &mismatches {
+            log::error!(target: &log_target, "{}: {}", name, mismatch);
+        }
+
+        report.errors = mismatches;
+        report.outcome = Outcome::Failed;
+        return report;
+    }
+
+    let patch = game.make_application_patch(domain, id);
+    let (existing_fields, new_fields) = application_fields(&application, &patch);
+    report.application = diff(&existing_fields, &new_fields);
+    apply(&log_target, name, "application", &report.application, push, &mut report.errors,
+        || async { discord.patch_current_application(bot_token, &patch).await.map(|_| ()) }).await;
+
+    let records = game.make_role_connection_records();
+    let (existing_records, existing_commands) = tokio::join!(
+        discord.get_metadata(application_id, bot_token),
+        discord.get_commands(application_id, bot_token)
+    );
+
+    match existing_records {
+        Ok(existing) => {
+            report.metadata = diff(&existing, &records);
+            apply(&log_target, name, "metadata", &report.metadata, push, &mut report.errors,
+                || discord.put_metadata(application_id, bot_token, &records)).await;
+        },
+        Err(e) => failed(&log_target, name, "get metadata", e, &mut report.errors)
+    }
+
+    match existing_commands {
+        Ok(existing) => {
+            report.commands = diff(&existing, commands);
+            apply(&log_target, name, "commands", &report.commands, push, &mut report.errors,
+                || discord.put_commands(application_id, bot_token, commands)).await;
+        },
+        Err(e) => failed(&log_target, name, "get commands", e, &mut report.errors)
+    }
+
+    report.outcome = match (report.errors.is_empty(), report.application.is_empty() && report.metadata.is_empty() && report.commands.is_empty(), push) {
+        (false, _, _) => Outcome::Failed,
+        (true, true, _) => Outcome::Unchanged,
+        (true, false, false) => Outcome::Outdated,
+        (true, false, true) => Outcome::Updated
+    };
+
+    report
+}
+
+/// Replaces the whole list with `put` if there are `changes` and we're pushing.
+async fn apply<T: Record, F: Future<Output = Result<(), DiscordError>>>(log_target: &str, name: &str, what: &str, changes: &[Change<T>], push: bool, errors: &mut Vec<String>, put: impl FnOnce() -> F) {
+    if changes.is_empty() {
+        log::info!(target: log_target, "{} for {} up to date", what, name);
+    } else if push {
+        log::warn!(target: log_target, "Existing and new {} for {} do not match! Updating...", what, name);
+
+        match put().await {
+            Ok(()) => log::info!(target: log_target, "Updated {} for {}", what, name),
+            Err(e) => failed(log_target, name, &format!("put {what}"), e, errors)
+        }
+    } else {
+        log::warn!(target: log_target, "Existing and new {} for {} do not match", what, name);
+    }
+}
+
+fn failed(log_target: &str, name: &str, action: &str, e: DiscordError, errors: &mut Vec<String>) {
+    log::error!(target: log_target, "{} ({}): {}", name, action, e);
+    errors.push(format!("{action}: {e}"));
+}
+
+/// Writes a game config from the name, profile and metadata the application
+/// with `suffix`'s credentials has now. Every key's conditions are left as a
+/// placeholder for you to fill in, and the config won't load until they are.
+async fn pull(settings: &Settings, discord: &impl DiscordApi, id: &str, suffix: Option<String>, output: Option<PathBuf>) -> Result<(), Vec<String>> {
+    let suffix = suffix.unwrap_or_else(|| id.to_uppercase().replace('-', "_"));
+    let output = output.unwrap_or_else(|| settings.games_dir.clone().unwrap_or_else(|| "games".into()).join(format!("{id}.yml")));
+    if output.exists() {
+        return Err(vec![format!("{output:?} already exists, not overwriting it")]);
+    }
+
+    let secrets = app_secrets(settings, &suffix)?;
+    let application = discord.get_current_application(&secrets.bot_token).await
+        .map_err(|e| vec![format!("failed to get application: {e}")])?;
+    let mismatches = check_application(&application, &suffix, secrets.application_id, secrets.client_id);
+    if !mismatches.is_empty() {
+        return Err(mismatches);
+    }
+
+    let records = discord.get_metadata(secrets.application_id, &secrets.bot_token).await
+        .map_err(|e| vec![format!("failed to get metadata: {e}")])?;
+    let (game, skipped) = skeleton(&application, &suffix, &records);
+
+    for key in skipped {
+        log::warn!(target: "soulfire::configure", "Leaving out {}, only boolean keys are supported", key);
+    }
+
+    let yaml = skeleton_yaml(&game).map_err(|e| vec![format!("failed to write game: {e}")])?;
+    fs::write(&output, yaml).map_err(|e| vec![format!("failed to write {output:?}: {e}")])?;
+    log::info!(target: "soulfire::configure", "Wrote {} key(s) to {:?}, fill in their conditions before validating or pushing", game.keys.len(), output);
+
+    Ok(())
 }
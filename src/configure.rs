@@ -0,0 +1,320 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::Serialize;
+
+use crate::{discord::{Application, ApplicationCommand, ApplicationPatch}, ApplicationProfile, Game, GameStatus, Key, KeyType, RoleConnectionMetadataRecord, RoleConnectionMetadataRecordType, UidConfig, UsernameConfig};
+
+/// Something Discord keeps a list of for each application, identified by a
+/// name that's unique within that list.
+pub trait Record: Serialize + Clone + PartialEq {
+    fn id(&self) -> &str;
+}
+
+impl Record for RoleConnectionMetadataRecord {
+    fn id(&self) -> &str {
+        &self.key
+    }
+}
+
+impl Record for ApplicationCommand {
+    fn id(&self) -> &str {
+        &self.name
+    }
+}
+
+/// One field of an application's profile.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct ApplicationField {
+    pub field: String,
+    pub value: serde_json::Value
+}
+
+impl Record for ApplicationField {
+    fn id(&self) -> &str {
+        &self.field
+    }
+}
+
+/// Every field `patch` sets, as `application` has it now and as it would be
+/// after the patch, for [`diff`].
+pub fn application_fields(application: &Application, patch: &ApplicationPatch) -> (Vec<ApplicationField>, Vec<ApplicationField>) {
+    let existing = serde_json::to_value(application).unwrap_or_default();
+    let serde_json::Value::Object(new) = serde_json::to_value(patch).unwrap_or_default() else {
+        return (Vec::new(), Vec::new());
+    };
+
+    new.into_iter()
+        .map(|(field, value)| (
+            ApplicationField { field: field.clone(), value: existing.get(&field).cloned().unwrap_or_default() },
+            ApplicationField { field, value }
+        ))
+        .unzip()
+}
+
+/// How one record differs between what Discord has and what's configured.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change<T> {
+    Added { new: T },
+    Removed { old: T },
+    Changed { old: T, new: T }
+}
+
+impl<T: Record> Change<T> {
+    pub fn id(&self) -> &str {
+        match self {
+            Change::Added { new } | Change::Changed { new, .. } => new.id(),
+            Change::Removed { old } => old.id()
+        }
+    }
+}
+
+impl<T: Record> fmt::Display for Change<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = |record: &T| serde_json::to_string(record).unwrap_or_default();
+
+        match self {
+            Change::Added { new } => write!(f, "+ {}: {}", new.id(), json(new)),
+            Change::Removed { old } => write!(f, "- {}: {}", old.id(), json(old)),
+            Change::Changed { old, new } => write!(f, "~ {}:\n    - {}\n    + {}", new.id(), json(old), json(new))
+        }
+    }
+}
+
+/// What happened to one game's application.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// It already matched its config.
+    Unchanged,
+    /// It didn't match, but nothing was pushed.
+    Outdated,
+    /// It didn't match, and now it does.
+    Updated,
+    Failed
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct GameReport {
+    pub game: String,
+    pub outcome: Outcome,
+    pub application: Vec<Change<ApplicationField>>,
+    pub metadata: Vec<Change<RoleConnectionMetadataRecord>>,
+    pub commands: Vec<Change<ApplicationCommand>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>
+}
+
+impl GameReport {
+    pub fn new(game: &str) -> Self {
+        Self { game: game.to_string(), outcome: Outcome::Unchanged, application: Vec::new(), metadata: Vec::new(), commands: Vec::new(), errors: Vec::new() }
+    }
+}
+
+/// Everything a run of soulfire-configure did, or the problems that kept it
+/// from starting.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Report {
+    pub games: Vec<GameReport>,
+    pub problems: Vec<String>
+}
+
+impl Report {
+    /// Whether anything went wrong, in which case the run should exit non-zero.
+    pub fn failed(&self) -> bool {
+        !self.problems.is_empty() || self.games.iter().any(|game| game.outcome == Outcome::Failed)
+    }
+}
+
+/// Every record that would change if `existing` were replaced by `new`,
+/// ordered by id.
+pub fn diff<T: Record>(existing: &[T], new: &[T]) -> Vec<Change<T>> {
+    let existing: BTreeMap<_, _> = existing.iter().map(|record| (record.id(), record)).collect();
+    let new: BTreeMap<_, _> = new.iter().map(|record| (record.id(), record)).collect();
+
+    let mut changes: Vec<_> = existing.iter()
+        .filter_map(|(id, old)| match new.get(id) {
+            None => Some(Change::Removed { old: (*old).clone() }),
+            Some(new) if new != old => Some(Change::Changed { old: (*old).clone(), new: (*new).clone() }),
+            Some(_) => None
+        })
+        .chain(new.iter()
+            .filter(|(id, _)| !existing.contains_key(*id))
+            .map(|(_, new)| Change::Added { new: (*new).clone() }))
+        .collect();
+
+    changes.sort_by(|a, b| a.id().cmp(b.id()));
+    changes
+}
+
+/// Checks that the application a game's `BOT_TOKEN_<SUFFIX>` belongs to is the
+/// one its `APP_ID_<SUFFIX>` and `CLIENT_ID_<SUFFIX>` name, returning every
+/// mismatch. Discord only answers a mismatched pair with 403s.
+pub fn check_application(application: &Application, suffix: &str, application_id: u64, client_id: u64) -> Vec<String> {
+    [("APP_ID", application_id), ("CLIENT_ID", client_id)].into_iter()
+        .filter(|(_, id)| application.id != id.to_string())
+        .map(|(name, id)| format!("BOT_TOKEN_{suffix} belongs to {} ({}), but {name}_{suffix} is {id}", application.name, application.id))
+        .collect()
+}
+
+/// A game config with an application's current name, profile and metadata,
+/// for whoever writes it to fill in the rest. Records Soulfire can't express
+/// as keys are left out and returned by their key.
+pub fn skeleton(application: &Application, suffix: &str, records: &[RoleConnectionMetadataRecord]) -> (Game, Vec<String>) {
+    let mut skipped = Vec::new();
+    let mut keys = BTreeMap::new();
+
+    for record in records {
+        match record.ty {
+            RoleConnectionMetadataRecordType::BoolEq => {
+                keys.insert(record.key.clone(), Key {
+                    ty: KeyType::BoolEq { conditions: Vec::new() },
+                    name: record.name.clone(),
+                    name_localizations: record.name_localizations.clone(),
+                    description: record.description.clone(),
+                    description_localizations: record.description_localizations.clone()
+                });
+            },
+            _ => skipped.push(record.key.clone())
+        }
+    }
+
+    let profile = ApplicationProfile {
+        description: Some(application.description.clone()).filter(|description| !description.is_empty()),
+        tags: application.tags.clone().filter(|tags| !tags.is_empty())
+    };
+
+    (Game {
+        name: application.name.clone(),
+        main_page: None,
+        suffix: suffix.to_string(),
+        uid: UidConfig {
+            max_length: 10
+        },
+        username: UsernameConfig {
+            optional: true,
+            max_length: 16
+        },
+        keys,
+        status: GameStatus::Active,
+        status_message: None,
+        aliases: Vec::new(),
+        application: Some(profile).filter(|profile| *profile != ApplicationProfile::default())
+    }, skipped)
+}
+
+/// What a skeleton has in place of each key's conditions. It isn't a list, so
+/// the config can't be loaded, pushed or served until someone replaces it.
+/// An empty list can't stand in, since it grants the key to everyone.
+pub const CONDITIONS_PLACEHOLDER: &str = "TODO list the conditions for this key";
+
+/// Writes a [`skeleton`] out as YAML, with [`CONDITIONS_PLACEHOLDER`] for
+/// every key's conditions.
+pub fn skeleton_yaml(game: &Game) -> Result<String, serde_yml::Error> {
+    Ok(serde_yml::to_string(game)?.replace("conditions: []", &format!("conditions: {CONDITIONS_PLACEHOLDER}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{discord::{Application, ApplicationPatch}, Game, RoleConnectionMetadataRecord, RoleConnectionMetadataRecordType};
+
+    use super::{application_fields, check_application, diff, skeleton, skeleton_yaml, ApplicationField, Change, GameReport, Outcome, Report, CONDITIONS_PLACEHOLDER};
+
+    fn application() -> Application {
+        Application { id: "1".to_string(), name: "Test".to_string(), ..Application::default() }
+    }
+
+    fn record(key: &str, name: &str, ty: RoleConnectionMetadataRecordType) -> RoleConnectionMetadataRecord {
+        RoleConnectionMetadataRecord {
+            ty,
+            key: key.to_string(),
+            name: name.to_string(),
+            name_localizations: None,
+            description: "A key".to_string(),
+            description_localizations: None
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let bool_eq = RoleConnectionMetadataRecordType::BoolEq;
+        let existing = [record("a", "A", bool_eq), record("b", "B", bool_eq), record("c", "C", bool_eq)];
+        let new = [record("d", "D", bool_eq), record("b", "Bee", bool_eq), record("a", "A", bool_eq)];
+
+        let changes = diff(&existing, &new);
+        assert_eq!(vec![
+            Change::Changed { old: existing[1].clone(), new: new[1].clone() },
+            Change::Removed { old: existing[2].clone() },
+            Change::Added { new: new[0].clone() }
+        ], changes);
+        assert_eq!(r#"- c: {"type":7,"key":"c","name":"C","description":"A key"}"#, changes[1].to_string());
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_check_application() {
+        let application = application();
+
+        assert!(check_application(&application, "TEST", 1, 1).is_empty());
+        assert_eq!(vec!["BOT_TOKEN_TEST belongs to Test (1), but CLIENT_ID_TEST is 2".to_string()], check_application(&application, "TEST", 1, 2));
+        assert_eq!(2, check_application(&application, "TEST", 2, 2).len());
+    }
+
+    #[test]
+    fn test_skeleton() {
+        let (game, skipped) = skeleton(&application(), "NEW", &[
+            record("is_na", "NA", RoleConnectionMetadataRecordType::BoolEq),
+            record("level", "Level", RoleConnectionMetadataRecordType::IntegerGtEq)
+        ]);
+
+        assert_eq!(vec!["is_na"], game.keys.keys().collect::<Vec<_>>());
+        assert_eq!(vec!["level".to_string()], skipped);
+        assert_eq!("Test", game.name);
+        assert!(game.application.is_none());
+        assert_eq!(vec![record("is_na", "NA", RoleConnectionMetadataRecordType::BoolEq)], game.make_role_connection_records());
+
+        let yaml = skeleton_yaml(&game).unwrap();
+        assert!(yaml.contains(CONDITIONS_PLACEHOLDER));
+        assert!(serde_yml::from_str::<Game>(&yaml).is_err());
+
+        let filled = yaml.replace(CONDITIONS_PLACEHOLDER, "[uid: { start: 600000000, end: 700000000 }]");
+        assert!(serde_yml::from_str::<Game>(&filled).is_ok());
+    }
+
+    #[test]
+    fn test_application_fields() {
+        let mut application = application();
+        application.description = "Old".to_string();
+        let patch = ApplicationPatch {
+            description: Some("New".to_string()),
+            tags: None,
+            role_connections_verification_url: Some("https://example.com/games/test/link".to_string())
+        };
+
+        let (existing, new) = application_fields(&application, &patch);
+        let field = |field: &str, value| ApplicationField { field: field.to_string(), value };
+        assert_eq!(vec![
+            Change::Changed { old: field("description", json!("Old")), new: field("description", json!("New")) },
+            Change::Changed { old: field("role_connections_verification_url", json!(null)), new: field("role_connections_verification_url", json!("https://example.com/games/test/link")) }
+        ], diff(&existing, &new));
+
+        application.description = "New".to_string();
+        application.role_connections_verification_url = patch.role_connections_verification_url.clone();
+        let (existing, new) = application_fields(&application, &patch);
+        assert!(diff(&existing, &new).is_empty());
+    }
+
+    #[test]
+    fn test_report() {
+        let mut report = Report { games: vec![GameReport::new("a"), GameReport::new("b")], problems: Vec::new() };
+        report.games[1].outcome = Outcome::Outdated;
+        assert!(!report.failed());
+        assert_eq!(serde_json::json!({ "game": "b", "outcome": "outdated", "application": [], "metadata": [], "commands": [] }), serde_json::to_value(&report.games[1]).unwrap());
+
+        report.games[0].outcome = Outcome::Failed;
+        assert!(report.failed());
+        assert!(Report { games: Vec::new(), problems: vec!["oops".to_string()] }.failed());
+    }
+}
@@ -0,0 +1,629 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, sync::{Mutex, MutexGuard}, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::{settings::{SettingError, Settings}, PutRoleConnectionInfo, RoleConnectionMetadataRecord};
+
+use self::ratelimit::RateLimiter;
+
+mod ratelimit;
+
+const USER_AGENT: &str = "DiscordBot (https://github.com/der-fruhling)";
+
+/// How [`ReqwestDiscordApi`] waits out rate limits and retries failures.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// How many times a request is retried after a rate limit or server error.
+    pub max_retries: u32,
+    /// How long to wait before retrying a server error. Each retry after the
+    /// first waits twice as long as the one before.
+    pub backoff: Duration,
+    /// The most a request made with a bot token spends waiting, in total.
+    /// Only soulfire-configure makes those, so nobody is stuck behind them.
+    pub max_wait: Duration,
+    /// The most a request made for a user spends waiting, in total, since
+    /// their browser is held up until it finishes.
+    pub max_user_wait: Duration
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: Duration::from_millis(500),
+            max_wait: Duration::from_secs(60),
+            max_user_wait: Duration::from_secs(5)
+        }
+    }
+}
+
+/// Where Discord lives. Overriding this allows pointing Soulfire at a local
+/// stand-in, or moving to a newer API version.
+#[derive(Clone, Debug)]
+pub struct DiscordConfig {
+    pub base_url: String,
+    pub api_version: u32
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://discord.com".to_string(),
+            api_version: 10
+        }
+    }
+}
+
+impl DiscordConfig {
+    /// Reads `discord_base_url` and `discord_api_version`, falling back to
+    /// the real Discord for anything unset.
+    pub fn from_settings(settings: &Settings) -> Result<Self, SettingError> {
+        let default = Self::default();
+
+        Ok(Self {
+            base_url: settings.discord_base_url.as_deref().map(|url| url.trim_end_matches('/').to_string()).unwrap_or(default.base_url),
+            api_version: settings.discord_api_version.as_deref()
+                .map(|v| v.parse().map_err(|_| SettingError::Invalid("DISCORD_API_VERSION".to_string(), "must be a number")))
+                .transpose()?
+                .unwrap_or(default.api_version)
+        })
+    }
+
+    pub fn api_url(&self) -> String {
+        format!("{}/api/v{}", self.base_url, self.api_version)
+    }
+
+    pub fn authorize_url(&self) -> String {
+        format!("{}/oauth2/authorize", self.base_url)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DiscordError {
+    #[error("failed to reach Discord: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Discord responded with {status}: {body}")]
+    Status { status: u16, body: String },
+    #[error("failed to parse Discord's response: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("rate limited by Discord for {0:?}")]
+    RateLimited(Duration)
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct AccessToken {
+    pub access_token: String,
+    pub expires_in: u64,
+    pub scope: String
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct RoleConnection {
+    #[serde(default)]
+    pub platform_name: Option<String>,
+    #[serde(default)]
+    pub platform_username: Option<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>
+}
+
+/// The application a bot token belongs to.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Application {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub role_connections_verification_url: Option<String>
+}
+
+/// Changes to an application's profile. Unset fields are left alone.
+#[derive(Serialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ApplicationPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role_connections_verification_url: Option<String>
+}
+
+/// A slash command as Soulfire defines it in `commands.json`. Discord sends
+/// back more fields than this when listing commands, which are ignored so the
+/// two can be compared.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ApplicationCommand {
+    pub name: String,
+    #[serde(rename = "type", default = "ApplicationCommand::default_type")]
+    pub ty: u8,
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name_localizations: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description_localizations: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<CommandOption>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_member_permissions: Option<String>
+}
+
+impl ApplicationCommand {
+    /// `CHAT_INPUT`, a slash command.
+    fn default_type() -> u8 {
+        1
+    }
+}
+
+impl PartialOrd for ApplicationCommand {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ApplicationCommand {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.name.cmp(&other.name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct CommandOption {
+    #[serde(rename = "type")]
+    pub ty: u8,
+    pub name: String,
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name_localizations: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description_localizations: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub required: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub autocomplete: bool
+}
+
+/// Everything Soulfire asks of Discord. User calls take the user's OAuth
+/// access token, application calls take the application's bot token.
+#[async_trait]
+pub trait DiscordApi: Send + Sync {
+    async fn exchange_code(&self, client_id: u64, client_secret: &str, code: &str, redirect_uri: &str) -> Result<AccessToken, DiscordError>;
+    async fn put_role_connection(&self, application_id: u64, token: &str, info: &PutRoleConnectionInfo<'_>) -> Result<(), DiscordError>;
+    async fn get_role_connection(&self, application_id: u64, token: &str) -> Result<RoleConnection, DiscordError>;
+    async fn get_metadata(&self, application_id: u64, bot_token: &str) -> Result<Vec<RoleConnectionMetadataRecord>, DiscordError>;
+    async fn put_metadata(&self, application_id: u64, bot_token: &str, records: &[RoleConnectionMetadataRecord]) -> Result<(), DiscordError>;
+    async fn get_commands(&self, application_id: u64, bot_token: &str) -> Result<Vec<ApplicationCommand>, DiscordError>;
+    /// Replaces every global command the application has with `commands`.
+    async fn put_commands(&self, application_id: u64, bot_token: &str, commands: &[ApplicationCommand]) -> Result<(), DiscordError>;
+    async fn get_current_application(&self, bot_token: &str) -> Result<Application, DiscordError>;
+    async fn patch_current_application(&self, bot_token: &str, patch: &ApplicationPatch) -> Result<Application, DiscordError>;
+}
+
+/// Talks to the real Discord API over HTTP, waiting out rate limits and
+/// retrying server errors a few times before giving up. Only requests that
+/// are safe to repeat are retried after a server error, since Discord may
+/// have acted on them anyway.
+pub struct ReqwestDiscordApi {
+    client: reqwest::Client,
+    api_url: String,
+    limiter: RateLimiter,
+    retries: RetryPolicy
+}
+
+impl Default for ReqwestDiscordApi {
+    fn default() -> Self {
+        Self::new(reqwest::Client::default(), &DiscordConfig::default())
+    }
+}
+
+impl ReqwestDiscordApi {
+    pub fn new(client: reqwest::Client, config: &DiscordConfig) -> Self {
+        Self { client, api_url: config.api_url(), limiter: RateLimiter::default(), retries: RetryPolicy::default() }
+    }
+
+    pub fn with_retries(mut self, retries: RetryPolicy) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client.request(method, format!("{}{path}", self.api_url))
+            .header("User-Agent", USER_AGENT)
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<String, DiscordError> {
+        let request = request.build()?;
+        let route = format!("{} {}", request.method(), request.url().path());
+        let auth = ratelimit::auth_key(request.headers());
+        let bot = request.headers().get("Authorization").is_some_and(|auth| auth.as_bytes().starts_with(b"Bot "));
+        let idempotent = [Method::GET, Method::PUT, Method::DELETE].contains(request.method());
+        let deadline = Instant::now() + if bot { self.retries.max_wait } else { self.retries.max_user_wait };
+        let mut attempt = 0;
+
+        loop {
+            let delay = self.limiter.delay(auth, &route);
+            if Instant::now() + delay > deadline {
+                return Err(DiscordError::RateLimited(delay));
+            }
+
+            tokio::time::sleep(delay).await;
+
+            // only fails for streaming bodies, which are never used here
+            let res = self.client.execute(request.try_clone().unwrap()).await?;
+            let status = res.status();
+            let headers = res.headers().clone();
+            let body = res.text().await?;
+            self.limiter.update(auth, &route, &headers);
+
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                let (retry_after, global) = ratelimit::retry_after(&headers, &body);
+                log::warn!("rate limited on {route} for {retry_after:?} (global: {global})");
+                self.limiter.block(auth, &route, global, retry_after);
+            } else if status.is_server_error() && idempotent {
+                log::warn!("{route} failed with {status}, attempt {}", attempt + 1);
+            } else if !status.is_success() {
+                return Err(DiscordError::Status { status: status.as_u16(), body });
+            } else {
+                return Ok(body);
+            }
+
+            let backoff = if status.is_server_error() { self.retries.backoff * 2u32.pow(attempt) } else { Duration::ZERO };
+            if attempt == self.retries.max_retries || Instant::now() + backoff > deadline {
+                return Err(DiscordError::Status { status: status.as_u16(), body });
+            }
+
+            tokio::time::sleep(backoff).await;
+
+            attempt += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl DiscordApi for ReqwestDiscordApi {
+    async fn exchange_code(&self, client_id: u64, client_secret: &str, code: &str, redirect_uri: &str) -> Result<AccessToken, DiscordError> {
+        let body = self.send(self.request(Method::POST, "/oauth2/token")
+            .body(format!("grant_type=authorization_code&code={}&redirect_uri={}", urlencoding::encode(code), urlencoding::encode(redirect_uri)))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .basic_auth(client_id, Some(client_secret))).await?;
+
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    async fn put_role_connection(&self, application_id: u64, token: &str, info: &PutRoleConnectionInfo<'_>) -> Result<(), DiscordError> {
+        self.send(self.request(Method::PUT, &format!("/users/@me/applications/{application_id}/role-connection"))
+            .body(serde_json::to_string(info)?)
+            .header("Content-Type", "application/json")
+            .bearer_auth(token)).await?;
+
+        Ok(())
+    }
+
+    async fn get_role_connection(&self, application_id: u64, token: &str) -> Result<RoleConnection, DiscordError> {
+        let body = self.send(self.request(Method::GET, &format!("/users/@me/applications/{application_id}/role-connection"))
+            .bearer_auth(token)).await?;
+
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    async fn get_metadata(&self, application_id: u64, bot_token: &str) -> Result<Vec<RoleConnectionMetadataRecord>, DiscordError> {
+        let body = self.send(self.request(Method::GET, &format!("/applications/{application_id}/role-connections/metadata"))
+            .header("Authorization", format!("Bot {bot_token}"))).await?;
+
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    async fn put_metadata(&self, application_id: u64, bot_token: &str, records: &[RoleConnectionMetadataRecord]) -> Result<(), DiscordError> {
+        self.send(self.request(Method::PUT, &format!("/applications/{application_id}/role-connections/metadata"))
+            .body(serde_json::to_string(records)?)
+            .header("Authorization", format!("Bot {bot_token}"))
+            .header("Content-Type", "application/json")).await?;
+
+        Ok(())
+    }
+
+    async fn get_commands(&self, application_id: u64, bot_token: &str) -> Result<Vec<ApplicationCommand>, DiscordError> {
+        let body = self.send(self.request(Method::GET, &format!("/applications/{application_id}/commands?with_localizations=true"))
+            .header("Authorization", format!("Bot {bot_token}"))).await?;
+
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    async fn put_commands(&self, application_id: u64, bot_token: &str, commands: &[ApplicationCommand]) -> Result<(), DiscordError> {
+        self.send(self.request(Method::PUT, &format!("/applications/{application_id}/commands"))
+            .body(serde_json::to_string(commands)?)
+            .header("Authorization", format!("Bot {bot_token}"))
+            .header("Content-Type", "application/json")).await?;
+
+        Ok(())
+    }
+
+    async fn get_current_application(&self, bot_token: &str) -> Result<Application, DiscordError> {
+        let body = self.send(self.request(Method::GET, "/applications/@me")
+            .header("Authorization", format!("Bot {bot_token}"))).await?;
+
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    async fn patch_current_application(&self, bot_token: &str, patch: &ApplicationPatch) -> Result<Application, DiscordError> {
+        let body = self.send(self.request(Method::PATCH, "/applications/@me")
+            .body(serde_json::to_string(patch)?)
+            .header("Authorization", format!("Bot {bot_token}"))
+            .header("Content-Type", "application/json")).await?;
+
+        Ok(serde_json::from_str(&body)?)
+    }
+}
+
+/// What the mock Discord knows about. Tests may inspect or modify it freely
+/// through [`MockDiscordApi::state`].
+#[derive(Default)]
+pub struct MockState {
+    /// Authorization codes that can be exchanged, and the token each one gives.
+    pub codes: HashMap<String, AccessToken>,
+    /// Access tokens that have been handed out and not revoked.
+    pub tokens: HashSet<String>,
+    /// Role connections as they were last sent, keyed by application and access token.
+    pub role_connections: HashMap<(u64, String), serde_json::Value>,
+    pub metadata: HashMap<u64, Vec<RoleConnectionMetadataRecord>>,
+    pub commands: HashMap<u64, Vec<ApplicationCommand>>,
+    /// The application each bot token belongs to.
+    pub applications: HashMap<String, Application>,
+    /// Statuses to fail the next calls with, in order, regardless of what they are.
+    pub failures: VecDeque<u16>
+}
+
+/// An in-process stand-in for Discord, so the server can run without the network.
+#[derive(Default)]
+pub struct MockDiscordApi {
+    state: Mutex<MockState>
+}
+
+impl MockDiscordApi {
+    pub fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Makes `code` exchangeable for an access token called `token`.
+    pub fn with_code(self, code: &str, token: &str) -> Self {
+        self.state().codes.insert(code.to_string(), AccessToken {
+            access_token: token.to_string(),
+            expires_in: 604800,
+            scope: "role_connections.write".to_string()
+        });
+
+        self
+    }
+
+    fn begin(&self) -> Result<MutexGuard<'_, MockState>, DiscordError> {
+        let mut state = self.state();
+
+        match state.failures.pop_front() {
+            Some(status) => Err(DiscordError::Status { status, body: "{\"message\": \"mock failure\"}".to_string() }),
+            None => Ok(state)
+        }
+    }
+
+    fn authorize(state: &MockState, token: &str) -> Result<(), DiscordError> {
+        if state.tokens.contains(token) {
+            Ok(())
+        } else {
+            Err(Self::unauthorized())
+        }
+    }
+
+    fn unauthorized() -> DiscordError {
+        DiscordError::Status { status: 401, body: "{\"message\": \"401: Unauthorized\", \"code\": 0}".to_string() }
+    }
+}
+
+#[async_trait]
+impl DiscordApi for MockDiscordApi {
+    async fn exchange_code(&self, _client_id: u64, _client_secret: &str, code: &str, _redirect_uri: &str) -> Result<AccessToken, DiscordError> {
+        let mut state = self.begin()?;
+        let token = state.codes.remove(code)
+            .ok_or_else(|| DiscordError::Status { status: 400, body: "{\"error\": \"invalid_grant\"}".to_string() })?;
+
+        state.tokens.insert(token.access_token.clone());
+        Ok(token)
+    }
+
+    async fn put_role_connection(&self, application_id: u64, token: &str, info: &PutRoleConnectionInfo<'_>) -> Result<(), DiscordError> {
+        let mut state = self.begin()?;
+        Self::authorize(&state, token)?;
+
+        state.role_connections.insert((application_id, token.to_string()), serde_json::to_value(info)?);
+        Ok(())
+    }
+
+    async fn get_role_connection(&self, application_id: u64, token: &str) -> Result<RoleConnection, DiscordError> {
+        let state = self.begin()?;
+        Self::authorize(&state, token)?;
+
+        match state.role_connections.get(&(application_id, token.to_string())) {
+            Some(value) => Ok(serde_json::from_value(value.clone())?),
+            None => Ok(RoleConnection::default())
+        }
+    }
+
+    async fn get_metadata(&self, application_id: u64, _bot_token: &str) -> Result<Vec<RoleConnectionMetadataRecord>, DiscordError> {
+        let state = self.begin()?;
+        Ok(state.metadata.get(&application_id).cloned().unwrap_or_default())
+    }
+
+    async fn put_metadata(&self, application_id: u64, _bot_token: &str, records: &[RoleConnectionMetadataRecord]) -> Result<(), DiscordError> {
+        let mut state = self.begin()?;
+        state.metadata.insert(application_id, records.to_vec());
+        Ok(())
+    }
+
+    async fn get_commands(&self, application_id: u64, _bot_token: &str) -> Result<Vec<ApplicationCommand>, DiscordError> {
+        let state = self.begin()?;
+        Ok(state.commands.get(&application_id).cloned().unwrap_or_default())
+    }
+
+    async fn put_commands(&self, application_id: u64, _bot_token: &str, commands: &[ApplicationCommand]) -> Result<(), DiscordError> {
+        let mut state = self.begin()?;
+        state.commands.insert(application_id, commands.to_vec());
+        Ok(())
+    }
+
+    async fn get_current_application(&self, bot_token: &str) -> Result<Application, DiscordError> {
+        let state = self.begin()?;
+        state.applications.get(bot_token)
+            .cloned()
+            .ok_or_else(Self::unauthorized)
+    }
+
+    async fn patch_current_application(&self, bot_token: &str, patch: &ApplicationPatch) -> Result<Application, DiscordError> {
+        let mut state = self.begin()?;
+        let application = state.applications.get_mut(bot_token).ok_or_else(Self::unauthorized)?;
+
+        if let Some(description) = &patch.description {
+            application.description = description.clone();
+        }
+
+        if let Some(tags) = &patch.tags {
+            application.tags = Some(tags.clone());
+        }
+
+        if let Some(url) = &patch.role_connections_verification_url {
+            application.role_connections_verification_url = Some(url.clone());
+        }
+
+        Ok(application.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, io::{BufRead, BufReader, Write}, net::TcpListener, thread::{self, JoinHandle}, time::Duration};
+
+    use crate::{Game, GameStatus, UidConfig, UsernameConfig};
+
+    use super::{Application, ApplicationCommand, ApplicationPatch, DiscordApi, DiscordConfig, DiscordError, MockDiscordApi, ReqwestDiscordApi, RetryPolicy};
+
+    /// Serves `responses` in order, one per connection, on a local port,
+    /// returning the `Authorization` header of each request it answered.
+    fn stub_discord(responses: Vec<(u16, &'static str, &'static str)>) -> (DiscordConfig, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = DiscordConfig { base_url: format!("http://{}", listener.local_addr().unwrap()), api_version: 10 };
+
+        let server = thread::spawn(move || responses.into_iter().map(|(status, headers, body)| {
+            let (mut stream, _) = listener.accept().unwrap();
+            let auth = BufReader::new(&stream).lines()
+                .map(Result::unwrap)
+                .take_while(|line| !line.is_empty())
+                .find_map(|line| Some(line.strip_prefix("authorization: ")?.to_string()))
+                .unwrap_or_default();
+
+            write!(stream, "HTTP/1.1 {status} Stub\r\nContent-Length: {}\r\nConnection: close\r\n{headers}\r\n{body}", body.len()).unwrap();
+            auth
+        }).collect());
+
+        (config, server)
+    }
+
+    fn stub_api(config: &DiscordConfig) -> ReqwestDiscordApi {
+        ReqwestDiscordApi::new(reqwest::Client::default(), config)
+            .with_retries(RetryPolicy { backoff: Duration::from_millis(1), ..RetryPolicy::default() })
+    }
+
+    #[tokio::test]
+    async fn test_retries() {
+        let retries = RetryPolicy::default().max_retries as usize;
+
+        let (config, server) = stub_discord(vec![
+            (429, "Retry-After: 0.1\r\nX-RateLimit-Global: true\r\n", r#"{"message": "You are being rate limited.", "retry_after": 0.1, "global": true}"#),
+            (200, "", r#"{"platform_username": "123"}"#)
+        ]);
+        let connection = stub_api(&config).get_role_connection(1, "token").await.unwrap();
+        assert_eq!(Some("123"), connection.platform_username.as_deref());
+        assert_eq!(vec!["Bearer token"; 2], server.join().unwrap());
+
+        let (config, server) = stub_discord(vec![(503, "", "unavailable"); retries + 1]);
+        assert!(matches!(stub_api(&config).get_role_connection(1, "token").await, Err(DiscordError::Status { status: 503, .. })));
+        assert_eq!(retries + 1, server.join().unwrap().len());
+
+        // the code may already have been redeemed, so trying it again can only fail
+        let (config, server) = stub_discord(vec![(503, "", "unavailable")]);
+        assert!(matches!(stub_api(&config).exchange_code(1, "secret", "code", "https://example.com").await, Err(DiscordError::Status { status: 503, .. })));
+        assert_eq!(1, server.join().unwrap().len());
+
+        // a user shouldn't be kept waiting for this long
+        let (config, server) = stub_discord(vec![(429, "Retry-After: 30\r\n", r#"{"retry_after": 30}"#)]);
+        assert!(matches!(stub_api(&config).get_role_connection(1, "token").await, Err(DiscordError::RateLimited(_))));
+        assert_eq!(1, server.join().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn test_mock_role_connection() {
+        let game = Game {
+            name: "Test".to_string(),
+            main_page: None,
+            suffix: "TEST".to_string(),
+            uid: UidConfig { max_length: 10 },
+            username: UsernameConfig { optional: true, max_length: 16 },
+            keys: BTreeMap::default(),
+            status: GameStatus::Active,
+            status_message: None,
+            aliases: Vec::new(),
+            application: None
+        };
+
+        let discord = MockDiscordApi::default().with_code("code", "token");
+        let token = discord.exchange_code(1, "secret", "code", "https://example.com").await.unwrap();
+        assert!(discord.exchange_code(1, "secret", "code", "https://example.com").await.is_err());
+
+        discord.put_role_connection(1, &token.access_token, &game.make_role_connection_info(123, "")).await.unwrap();
+        let connection = discord.get_role_connection(1, &token.access_token).await.unwrap();
+        assert_eq!(Some("123"), connection.platform_username.as_deref());
+
+        discord.state().tokens.clear();
+        assert!(matches!(
+            discord.put_role_connection(1, &token.access_token, &game.make_role_connection_info(123, "")).await,
+            Err(DiscordError::Status { status: 401, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_mock_application() {
+        let discord = MockDiscordApi::default();
+        discord.state().applications.insert("bot".to_string(), Application { id: "1".to_string(), name: "Test".to_string(), ..Application::default() });
+
+        let patched = discord.patch_current_application("bot", &ApplicationPatch {
+            tags: Some(vec!["rpg".to_string()]),
+            ..ApplicationPatch::default()
+        }).await.unwrap();
+        assert_eq!(Some(vec!["rpg".to_string()]), patched.tags);
+        assert_eq!(patched, discord.get_current_application("bot").await.unwrap());
+        assert!(matches!(discord.get_current_application("other").await, Err(DiscordError::Status { status: 401, .. })));
+    }
+
+    #[test]
+    fn test_command_listing_matches_definition() {
+        let defined: Vec<ApplicationCommand> = serde_json::from_str(include_str!("../commands.json")).unwrap();
+        let listed: Vec<ApplicationCommand> = serde_json::from_value(serde_json::json!([{
+            "id": "1",
+            "application_id": "2",
+            "version": "3",
+            "type": 1,
+            "name": "help",
+            "name_localizations": null,
+            "description": "Get help about using Soulfire",
+            "description_localizations": null,
+            "default_member_permissions": null,
+            "dm_permission": true,
+            "nsfw": false
+        }])).unwrap();
+
+        assert_eq!(defined[0], listed[0]);
+        assert_ne!(defined, listed);
+    }
+}
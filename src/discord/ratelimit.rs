@@ -0,0 +1,200 @@
+use std::{collections::HashMap, hash::{DefaultHasher, Hash, Hasher}, sync::Mutex, time::Duration};
+
+use reqwest::header::HeaderMap;
+use tokio::time::Instant;
+
+/// Tracks Discord's rate limits so requests wait out a limit instead of
+/// running into it. Routes are mapped to the buckets Discord reports for
+/// them, since several routes may share one bucket.
+///
+/// Discord limits each token separately, so every route, bucket and global
+/// limit is kept per [`auth_key`]. One user running into a limit doesn't
+/// hold up anyone else. Limits are forgotten once they've passed, along with
+/// the routes mapped to them, so only the tokens that are being held up
+/// right now take any space.
+#[derive(Default)]
+pub struct RateLimiter {
+    state: Mutex<RateLimitState>
+}
+
+#[derive(Default)]
+struct RateLimitState {
+    global_until: HashMap<u64, Instant>,
+    routes: HashMap<(u64, String), String>,
+    buckets: HashMap<(u64, String), Instant>
+}
+
+/// Identifies whoever a request is sent as by a hash of its `Authorization`
+/// header, so the limiter never holds on to the token itself.
+pub fn auth_key(headers: &HeaderMap) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    headers.get("Authorization").map(|v| v.as_bytes()).hash(&mut hasher);
+    hasher.finish()
+}
+
+impl RateLimiter {
+    /// How long a request on `route` sent as `auth` should wait before it is sent.
+    pub fn delay(&self, auth: u64, route: &str) -> Duration {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let bucket = state.routes.get(&(auth, route.to_string()))
+            .and_then(|bucket| state.buckets.get(&(auth, bucket.clone())));
+
+        [state.global_until.get(&auth), bucket].into_iter()
+            .flatten()
+            .map(|until| until.saturating_duration_since(now))
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Records the bucket state Discord reported in a response to `route`.
+    pub fn update(&self, auth: u64, route: &str, headers: &HeaderMap) {
+        let Some(bucket) = header(headers, "X-RateLimit-Bucket") else { return };
+        let mut state = self.state.lock().unwrap();
+        state.routes.insert((auth, route.to_string()), bucket.to_string());
+        let bucket = (auth, bucket.to_string());
+
+        let remaining = header(headers, "X-RateLimit-Remaining").and_then(|v| v.parse::<u64>().ok());
+        let reset_after = header(headers, "X-RateLimit-Reset-After").and_then(seconds);
+
+        match (remaining, reset_after) {
+            (Some(0), Some(reset_after)) => { state.buckets.insert(bucket, Instant::now() + reset_after); },
+            _ => { state.buckets.remove(&bucket); }
+        }
+
+        state.sweep();
+    }
+
+    /// Blocks `route`, or every route if the limit is global, for
+    /// `retry_after`. Either way only requests sent as `auth` are held up.
+    pub fn block(&self, auth: u64, route: &str, global: bool, retry_after: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.sweep();
+        let until = Instant::now() + retry_after;
+
+        if global {
+            state.global_until.insert(auth, until);
+        } else {
+            let route = (auth, route.to_string());
+            let bucket = state.routes.get(&route).cloned().unwrap_or_else(|| route.1.clone());
+            state.routes.insert(route, bucket.clone());
+            state.buckets.insert((auth, bucket), until);
+        }
+    }
+}
+
+impl RateLimitState {
+    /// Drops every limit that has passed, and every route whose bucket
+    /// isn't limited any more.
+    fn sweep(&mut self) {
+        let now = Instant::now();
+        self.global_until.retain(|_, until| *until > now);
+        self.buckets.retain(|_, until| *until > now);
+
+        let buckets = &self.buckets;
+        self.routes.retain(|(auth, _), bucket| buckets.contains_key(&(*auth, bucket.clone())));
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok()
+}
+
+/// Parses Discord's fractional second values.
+pub fn seconds(value: &str) -> Option<Duration> {
+    value.trim().parse::<f64>().ok()
+        .filter(|v| v.is_finite() && *v >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// How long to wait before retrying after a 429, and whether the limit is
+/// global. Discord sends these both as headers and in the body.
+pub fn retry_after(headers: &HeaderMap, body: &str) -> (Duration, bool) {
+    #[derive(serde::Deserialize)]
+    struct RateLimited {
+        retry_after: f64,
+        #[serde(default)]
+        global: bool
+    }
+
+    let from_body = serde_json::from_str::<RateLimited>(body).ok();
+    let global = header(headers, "X-RateLimit-Global").is_some_and(|v| v.eq_ignore_ascii_case("true"))
+        || header(headers, "X-RateLimit-Scope") == Some("global")
+        || from_body.as_ref().is_some_and(|b| b.global);
+    let retry_after = header(headers, "Retry-After").and_then(seconds)
+        .or_else(|| from_body.and_then(|b| Duration::try_from_secs_f64(b.retry_after).ok()))
+        .unwrap_or(Duration::from_secs(1));
+
+    (retry_after, global)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::{auth_key, retry_after, RateLimiter};
+
+    #[test]
+    fn test_buckets() {
+        let limiter = RateLimiter::default();
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RateLimit-Bucket", HeaderValue::from_static("abc"));
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from_static("0"));
+        headers.insert("X-RateLimit-Reset-After", HeaderValue::from_static("2.5"));
+
+        limiter.update(1, "PUT /a", &headers);
+        assert!(limiter.delay(1, "PUT /a") > Duration::from_secs(2));
+        assert_eq!(Duration::ZERO, limiter.delay(1, "PUT /b"));
+        assert_eq!(Duration::ZERO, limiter.delay(2, "PUT /a"));
+
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from_static("4"));
+        limiter.update(1, "PUT /a", &headers);
+        assert_eq!(Duration::ZERO, limiter.delay(1, "PUT /a"));
+
+        limiter.block(1, "PUT /a", true, Duration::from_secs(5));
+        assert!(limiter.delay(1, "PUT /b") > Duration::from_secs(4));
+        assert_eq!(Duration::ZERO, limiter.delay(2, "PUT /b"));
+    }
+
+    #[test]
+    fn test_expired_limits() {
+        let limiter = RateLimiter::default();
+        limiter.block(1, "PUT /a", true, Duration::ZERO);
+        limiter.block(2, "PUT /a", false, Duration::ZERO);
+        limiter.block(3, "PUT /a", false, Duration::from_secs(5));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RateLimit-Bucket", HeaderValue::from_static("abc"));
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from_static("4"));
+        limiter.update(4, "PUT /a", &headers);
+
+        let state = limiter.state.lock().unwrap();
+        assert!(state.global_until.is_empty());
+        assert_eq!(vec![&(3, "PUT /a".to_string())], state.routes.keys().collect::<Vec<_>>());
+        assert_eq!(1, state.buckets.len());
+    }
+
+    #[test]
+    fn test_auth_key() {
+        let auth = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("Authorization", HeaderValue::from_static(value));
+            auth_key(&headers)
+        };
+
+        assert_eq!(auth("Bearer a"), auth("Bearer a"));
+        assert_ne!(auth("Bearer a"), auth("Bearer b"));
+        assert_ne!(auth("Bearer a"), auth_key(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!((Duration::from_millis(1500), true), retry_after(&headers, r#"{"message": "You are being rate limited.", "retry_after": 1.5, "global": true}"#));
+
+        headers.insert("Retry-After", HeaderValue::from_static("3"));
+        assert_eq!((Duration::from_secs(3), false), retry_after(&headers, ""));
+    }
+}
@@ -0,0 +1,215 @@
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Serialize, Deserialize};
+use serde_repr::{Serialize_repr, Deserialize_repr};
+
+/// Discord allows at most this many buttons in a row, and this many rows.
+const MAX_ROW_LENGTH: usize = 5;
+const MAX_ROWS: usize = 5;
+/// Discord shows at most this many autocomplete suggestions.
+const MAX_CHOICES: usize = 25;
+
+/// Checks that an interaction really came from Discord, which signs the
+/// request timestamp followed by the body with the application's key.
+pub fn verify_signature(key: &VerifyingKey, signature: &str, timestamp: &str, body: &str) -> bool {
+    let Some(signature) = hex::decode(signature).ok().and_then(|s| Signature::from_slice(&s).ok()) else {
+        return false;
+    };
+
+    key.verify(&[timestamp.as_bytes(), body.as_bytes()].concat(), &signature).is_ok()
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Interaction {
+    #[serde(rename = "type")]
+    pub ty: InteractionType,
+    #[serde(default)]
+    pub data: Option<CommandData>,
+    /// The language of the user who sent this.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// The language of the server it was sent in, if any.
+    #[serde(default)]
+    pub guild_locale: Option<String>
+}
+
+#[derive(Serialize_repr, Deserialize_repr, Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum InteractionType {
+    Ping = 1,
+    ApplicationCommand = 2,
+    MessageComponent = 3,
+    ApplicationCommandAutocomplete = 4,
+    ModalSubmit = 5
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommandData {
+    pub name: String,
+    #[serde(default)]
+    pub options: Vec<CommandDataOption>
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommandDataOption {
+    pub name: String,
+    #[serde(default)]
+    pub value: Option<serde_json::Value>,
+    /// Set on the option the user is typing in, when autocompleting.
+    #[serde(default)]
+    pub focused: bool
+}
+
+impl CommandData {
+    fn value(&self, name: &str) -> Option<&serde_json::Value> {
+        self.options.iter()
+            .find(|option| option.name == name)
+            .and_then(|option| option.value.as_ref())
+    }
+
+    /// The value given for the string option `name`, if any.
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.value(name)?.as_str()
+    }
+
+    /// The value given for the integer option `name`, if any.
+    pub fn integer_option(&self, name: &str) -> Option<u64> {
+        self.value(name)?.as_u64()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct InteractionResponse {
+    #[serde(rename = "type")]
+    ty: ResponseType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<ResponseData>
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum ResponseData {
+    Message(MessageData),
+    Autocomplete { choices: Vec<Choice> }
+}
+
+#[derive(Serialize, Debug)]
+struct Choice {
+    name: String,
+    value: String
+}
+
+#[derive(Serialize_repr, Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+enum ResponseType {
+    Pong = 1,
+    ChannelMessageWithSource = 4,
+    ApplicationCommandAutocompleteResult = 8
+}
+
+#[derive(Serialize, Debug)]
+struct MessageData {
+    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    components: Vec<ActionRow>
+}
+
+/// Only visible to the user who ran the command.
+const EPHEMERAL: u64 = 1 << 6;
+
+#[derive(Serialize, Debug)]
+struct ActionRow {
+    #[serde(rename = "type")]
+    ty: u8,
+    components: Vec<Button>
+}
+
+#[derive(Serialize, Debug)]
+struct Button {
+    #[serde(rename = "type")]
+    ty: u8,
+    style: u8,
+    label: String,
+    url: String
+}
+
+/// A button that opens `url` in the user's browser.
+pub struct LinkButton {
+    pub label: String,
+    pub url: String
+}
+
+impl InteractionResponse {
+    pub fn pong() -> Self {
+        Self { ty: ResponseType::Pong, data: None }
+    }
+
+    /// A message replying to the interaction, with `buttons` laid out in as
+    /// many rows as they need. Buttons past what Discord allows are dropped.
+    pub fn message(content: impl Into<String>, ephemeral: bool, buttons: Vec<LinkButton>) -> Self {
+        let buttons: Vec<_> = buttons.into_iter()
+            .take(MAX_ROW_LENGTH * MAX_ROWS)
+            .map(|button| Button { ty: 2, style: 5, label: button.label, url: button.url })
+            .collect();
+        let mut rows: Vec<ActionRow> = Vec::new();
+
+        for button in buttons {
+            match rows.last_mut() {
+                Some(row) if row.components.len() < MAX_ROW_LENGTH => row.components.push(button),
+                _ => rows.push(ActionRow { ty: 1, components: vec![button] })
+            }
+        }
+
+        Self {
+            ty: ResponseType::ChannelMessageWithSource,
+            data: Some(ResponseData::Message(MessageData {
+                content: content.into(),
+                flags: ephemeral.then_some(EPHEMERAL),
+                components: rows
+            }))
+        }
+    }
+
+    /// Autocomplete suggestions, as pairs of what's shown and what's sent.
+    pub fn autocomplete(choices: impl IntoIterator<Item = (String, String)>) -> Self {
+        Self {
+            ty: ResponseType::ApplicationCommandAutocompleteResult,
+            data: Some(ResponseData::Autocomplete {
+                choices: choices.into_iter()
+                    .take(MAX_CHOICES)
+                    .map(|(name, value)| Choice { name, value })
+                    .collect()
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+    use serde_json::json;
+
+    use super::{verify_signature, InteractionResponse, LinkButton};
+
+    #[test]
+    fn test_verify_signature() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let signature = hex::encode(key.sign(b"123{\"type\":1}").to_bytes());
+
+        assert!(verify_signature(&key.verifying_key(), &signature, "123", "{\"type\":1}"));
+        assert!(!verify_signature(&key.verifying_key(), &signature, "124", "{\"type\":1}"));
+        assert!(!verify_signature(&key.verifying_key(), "not hex", "123", "{\"type\":1}"));
+    }
+
+    #[test]
+    fn test_button_rows() {
+        let buttons = (0..7).map(|i| LinkButton { label: i.to_string(), url: format!("https://example.com/{i}") }).collect();
+        let response = serde_json::to_value(InteractionResponse::message("hi", true, buttons)).unwrap();
+
+        assert_eq!(json!(64), response["data"]["flags"]);
+        assert_eq!(5, response["data"]["components"][0]["components"].as_array().unwrap().len());
+        assert_eq!(json!({ "type": 2, "style": 5, "label": "6", "url": "https://example.com/6" }), response["data"]["components"][1]["components"][1]);
+    }
+}
@@ -1,15 +1,26 @@
-use std::{collections::{BTreeMap, HashMap}, ops::Range, env};
+use std::{collections::{BTreeMap, HashMap}, ops::Range};
 
-use aes_gcm::{KeyInit, Aes256Gcm, AeadCore, aead::{OsRng, Aead, generic_array::sequence::Lengthen, AeadMut}, Nonce};
+use aes_gcm::{KeyInit, Aes256Gcm, AeadCore, aead::{OsRng, Aead, Payload}, Nonce};
 use base64::Engine;
 use hmac::Hmac;
-use jwt::{SignWithKey, VerifyWithKey};
-use lazy_static::lazy_static;
+use jwt::{Header, SignWithKey, Token, VerifyWithKey};
 use rustrict::CensorStr;
 use serde::{Serialize, Deserialize};
 use serde_repr::{Serialize_repr, Deserialize_repr};
 use sha2::Sha256;
 use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+
+use crate::{discord::ApplicationPatch, settings::{SettingError, Settings}};
+
+pub mod configure;
+pub mod discord;
+pub mod interactions;
+pub mod locales;
+pub mod registry;
+pub mod settings;
+pub mod store;
+pub mod webhooks;
 
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -19,7 +30,39 @@ pub struct Game {
     pub suffix: String,
     pub uid: UidConfig,
     pub username: UsernameConfig,
-    pub keys: BTreeMap<String, Key>
+    pub keys: BTreeMap<String, Key>,
+    #[serde(default)]
+    pub status: GameStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_message: Option<String>,
+    /// Other ids this game used to go by, which redirect to its current one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// How the game's Discord application presents itself, which
+    /// soulfire-configure keeps in sync.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application: Option<ApplicationProfile>
+}
+
+/// Profile fields of a game's Discord application. Anything unset is left
+/// as it is in the developer portal.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+pub struct ApplicationProfile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum GameStatus {
+    #[default]
+    Active,
+    /// Temporarily closed, nothing can be linked until it's active again.
+    Maintenance,
+    /// Closed for good. Users can only remove their connection.
+    Retired
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -45,6 +88,17 @@ pub struct Key {
     pub description_localizations: Option<HashMap<String, String>>
 }
 
+impl Key {
+    /// Whether a profile with `uid` meets every condition of this key.
+    pub fn matches(&self, uid: u64) -> bool {
+        match &self.ty {
+            KeyType::BoolEq { conditions } => conditions.iter().all(|c| match c {
+                KeyCondition::Uid(range) => range.contains(&uid),
+            })
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[serde(tag = "type")]
 pub enum KeyType {
@@ -84,15 +138,34 @@ impl Game {
             platform_username: if username.is_empty() { uid.to_string() } else { format!("{} ({})", username.censor(), uid) },
             metadata: HashMap::from_iter(self.keys.iter()
                 .map(|(k, v)| (k.as_str(), match &v.ty {
-                    KeyType::BoolEq { conditions } => if conditions.iter().all(|c| match c {
-                        KeyCondition::Uid(range) => range.contains(&uid),
-                    }) { "1" } else { "0" }.to_string(),
+                    KeyType::BoolEq { .. } => if v.matches(uid) { "1" } else { "0" }.to_string(),
                 })))
         }
     }
+
+    /// The changes that point the game's application at Soulfire, served from
+    /// `domain` with the game under `id`, and apply its profile.
+    pub fn make_application_patch(&self, domain: &str, id: &str) -> ApplicationPatch {
+        let profile = self.application.clone().unwrap_or_default();
+
+        ApplicationPatch {
+            description: profile.description,
+            tags: profile.tags,
+            role_connections_verification_url: Some(format!("https://{domain}/games/{id}/link"))
+        }
+    }
+
+    /// A role connection with no metadata, which meets no role requirements.
+    pub fn make_unlink_info(&self) -> PutRoleConnectionInfo<'_> {
+        PutRoleConnectionInfo {
+            platform_name: &self.name,
+            platform_username: String::new(),
+            metadata: HashMap::new()
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub struct RoleConnectionMetadataRecord {
     #[serde(rename = "type")]
     pub ty: RoleConnectionMetadataRecordType,
@@ -107,7 +180,7 @@ pub struct RoleConnectionMetadataRecord {
 
 impl PartialOrd for RoleConnectionMetadataRecord {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.key.cmp(&other.key))
+        Some(self.cmp(other))
     }
 }
 
@@ -117,7 +190,7 @@ impl Ord for RoleConnectionMetadataRecord {
     }
 }
 
-#[derive(Serialize_repr, Deserialize_repr, PartialEq, Eq, Debug)]
+#[derive(Serialize_repr, Deserialize_repr, Clone, Copy, PartialEq, Eq, Debug)]
 #[repr(usize)]
 #[non_exhaustive]
 pub enum RoleConnectionMetadataRecordType {
@@ -131,48 +204,227 @@ pub enum RoleConnectionMetadataRecordType {
     BoolNotEq = 8
 }
 
-lazy_static! {
-    static ref TOKEN_JWT_KEY: Hmac<Sha256> = Hmac::new_from_slice(&hex::decode(env::var("TOKEN_JWT_KEY").expect("No token JWT key!")).expect("Token JWT key must be hex!")).unwrap();
-    static ref TOKEN_CIPHER_KEY_BYTES: [u8; 32] = hex::decode(env::var("TOKEN_CIPHER_KEY").expect("No token cipher key!")).expect("Token cipher key must be hex!").try_into().unwrap();
-    static ref TOKEN_CIPHER_KEY: &'static aes_gcm::Key<Aes256Gcm> = TOKEN_CIPHER_KEY_BYTES.as_ref().into();
+/// One generation of the keys used to sign and encrypt tokens.
+pub struct TokenKeys {
+    jwt: Hmac<Sha256>,
+    cipher: aes_gcm::Key<Aes256Gcm>
 }
 
-pub fn generate_encrypted_key(token: &str) -> String {
-    let cipher = Aes256Gcm::new(&TOKEN_CIPHER_KEY);
-    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
-    let mut ciphertext = cipher.encrypt(&nonce, token.as_bytes()).unwrap();
+impl TokenKeys {
+    pub fn new(jwt: &[u8], cipher: [u8; 32]) -> Self {
+        Self {
+            jwt: Hmac::new_from_slice(jwt).unwrap(),
+            cipher: cipher.into()
+        }
+    }
+
+    /// Decodes the hex encoded keys from the settings named `jwt_name` and
+    /// `cipher_name`, reporting every one that is missing or malformed.
+    pub fn from_hex(jwt_name: &str, jwt: Option<&str>, cipher_name: &str, cipher: Option<&str>) -> Result<Self, Vec<SettingError>> {
+        let jwt = jwt.ok_or_else(|| SettingError::Missing(jwt_name.to_string()))
+            .and_then(|jwt| hex::decode(jwt).map_err(|_| SettingError::Invalid(jwt_name.to_string(), "must be hex")));
+        let cipher = cipher.ok_or_else(|| SettingError::Missing(cipher_name.to_string()))
+            .and_then(|cipher| hex::decode(cipher).ok()
+                .and_then(|cipher| cipher.try_into().ok())
+                .ok_or_else(|| SettingError::Invalid(cipher_name.to_string(), "must be 32 hex encoded bytes")));
 
-    let mut result = nonce.to_vec();
-    result.append(&mut ciphertext);
-    let claims = base64::engine::general_purpose::STANDARD_NO_PAD.encode(result);
-    claims.sign_with_key(&*TOKEN_JWT_KEY).unwrap()
+        match (jwt, cipher) {
+            (Ok(jwt), Ok(cipher)) => Ok(Self::new(&jwt, cipher)),
+            (jwt, cipher) => Err([jwt.err(), cipher.err()].into_iter().flatten().collect())
+        }
+    }
 }
 
-#[derive(Error, Debug)]
-#[error("invalid token")]
-pub struct InvalidToken;
+/// The set of keys tokens may be issued or verified with. New tokens are
+/// always issued with the active key; retired keys are only used to read
+/// tokens issued before a rotation, until they expire on their own.
+pub struct Keyring {
+    active: String,
+    keys: HashMap<String, TokenKeys>
+}
+
+impl Keyring {
+    pub fn new(active_id: &str, keys: TokenKeys) -> Self {
+        Self {
+            active: active_id.to_string(),
+            keys: HashMap::from([(active_id.to_string(), keys)])
+        }
+    }
+
+    pub fn with_retired(mut self, id: &str, keys: TokenKeys) -> Self {
+        if id != self.active {
+            self.keys.insert(id.to_string(), keys);
+        }
+
+        self
+    }
 
-pub fn decrypt_key(key: &str) -> Result<String, InvalidToken> {
-    let cipher = Aes256Gcm::new(&TOKEN_CIPHER_KEY);
-    let result: String = key.verify_with_key(&*TOKEN_JWT_KEY).map_err(|_| InvalidToken)?;
-    let decoded = base64::engine::general_purpose::STANDARD_NO_PAD.decode(result).map_err(|_| InvalidToken)?;
-    let nonce = Nonce::from_slice(&decoded[..12]);
-    let ciphertext = &decoded[12..];
+    /// Reads the active key from `token_key_id`, `token_jwt_key` and
+    /// `token_cipher_key`, and every generation in `retired_keys`, reporting
+    /// every key that is missing or malformed.
+    pub fn from_settings(settings: &Settings) -> Result<Self, Vec<SettingError>> {
+        let active = settings.token_key_id.as_deref().unwrap_or("0");
+        let mut errors = Vec::new();
+        let mut keyring = TokenKeys::from_hex("TOKEN_JWT_KEY", settings.token_jwt_key.as_deref(), "TOKEN_CIPHER_KEY", settings.token_cipher_key.as_deref())
+            .map(|keys| Self::new(active, keys))
+            .map_err(|e| errors.extend(e))
+            .ok();
 
-    let plaintext = cipher.decrypt(nonce, ciphertext).map_err(|_| InvalidToken)?;
-    Ok(String::from_utf8(plaintext).unwrap())
+        for (id, keys) in &settings.retired_keys {
+            match TokenKeys::from_hex(&format!("TOKEN_JWT_KEY_{id}"), keys.jwt_key.as_deref(), &format!("TOKEN_CIPHER_KEY_{id}"), keys.cipher_key.as_deref()) {
+                Ok(keys) => keyring = keyring.map(|keyring| keyring.with_retired(id, keys)),
+                Err(e) => errors.extend(e)
+            }
+        }
+
+        match keyring {
+            Some(keyring) if errors.is_empty() => Ok(keyring),
+            _ => Err(errors)
+        }
+    }
+
+    /// Encrypts `token` into an envelope that is only valid for `game` and
+    /// expires after `ttl`.
+    pub fn encrypt(&self, token: &str, game: &str, ttl: Duration) -> String {
+        let keys = &self.keys[&self.active];
+        let cipher = Aes256Gcm::new(&keys.cipher);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut ciphertext = cipher.encrypt(&nonce, Payload { msg: token.as_bytes(), aad: game.as_bytes() }).unwrap();
+
+        let mut result = nonce.to_vec();
+        result.append(&mut ciphertext);
+        let now = OffsetDateTime::now_utc();
+        let claims = TokenClaims {
+            v: TOKEN_VERSION,
+            iat: now.unix_timestamp(),
+            exp: (now + ttl).unix_timestamp(),
+            game: game.to_string(),
+            ct: base64::engine::general_purpose::STANDARD_NO_PAD.encode(result)
+        };
+        let header = Header {
+            key_id: Some(self.active.clone()),
+            ..Default::default()
+        };
+
+        Token::new(header, claims).sign_with_key(&keys.jwt).unwrap().as_str().to_string()
+    }
+
+    /// Decrypts a token issued by [`Keyring::encrypt`], checking that it was
+    /// issued for `game` and hasn't expired.
+    pub fn decrypt(&self, key: &str, game: &str) -> Result<String, InvalidToken> {
+        let token: Token<Header, TokenClaims, _> = Token::parse_unverified(key).map_err(|_| InvalidToken)?;
+        let keys = token.header().key_id.as_ref()
+            .and_then(|id| self.keys.get(id))
+            .ok_or(InvalidToken)?;
+        let claims: TokenClaims = key.verify_with_key(&keys.jwt).map_err(|_| InvalidToken)?;
+
+        if claims.v != TOKEN_VERSION || claims.game != game || claims.exp <= OffsetDateTime::now_utc().unix_timestamp() {
+            return Err(InvalidToken);
+        }
+
+        let cipher = Aes256Gcm::new(&keys.cipher);
+        let decoded = base64::engine::general_purpose::STANDARD_NO_PAD.decode(claims.ct).map_err(|_| InvalidToken)?;
+        if decoded.len() < 12 {
+            return Err(InvalidToken);
+        }
+
+        let (nonce, ciphertext) = decoded.split_at(12);
+        let plaintext = cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: game.as_bytes() }).map_err(|_| InvalidToken)?;
+        String::from_utf8(plaintext).map_err(|_| InvalidToken)
+    }
+}
+
+const TOKEN_VERSION: u8 = 1;
+
+/// The signed claims of a token envelope. `ct` holds the nonce and the
+/// encrypted token, base64 encoded.
+#[derive(Serialize, Deserialize)]
+struct TokenClaims {
+    v: u8,
+    iat: i64,
+    exp: i64,
+    game: String,
+    ct: String
 }
 
+#[derive(Error, Debug)]
+#[error("invalid token")]
+pub struct InvalidToken;
+
 #[cfg(test)]
 mod tests {
-    use crate::{generate_encrypted_key, decrypt_key};
+    use jwt::{Header, SignWithKey, Token};
+    use time::Duration;
+
+    use crate::{settings::{RetiredKeys, SettingError, Settings}, Keyring, TokenClaims, TokenKeys};
 
     #[test]
     fn test_encryption() {
+        let keyring = Keyring::from_settings(&Settings {
+            token_jwt_key: Some(hex::encode([1; 32])),
+            token_cipher_key: Some(hex::encode([2; 32])),
+            ..Settings::default()
+        }).unwrap();
+
         let token = hex::encode("hello world this is a test token lmao oo it's long even longer than a real token oh boy");
-        let encoded = generate_encrypted_key(&token);
-        let decoded = decrypt_key(&encoded).unwrap();
+        let encoded = keyring.encrypt(&token, "test", Duration::minutes(5));
+        let decoded = keyring.decrypt(&encoded, "test").unwrap();
 
         assert_eq!(token, decoded)
     }
+
+    #[test]
+    fn test_keyring_settings() {
+        let mut settings = Settings {
+            token_cipher_key: Some("not hex".to_string()),
+            ..Settings::default()
+        };
+        settings.retired_keys.insert("OLD".to_string(), RetiredKeys { jwt_key: Some("00".to_string()), cipher_key: None });
+
+        let errors = Keyring::from_settings(&settings).err().unwrap();
+        assert!(matches!(&errors[..], [
+            SettingError::Missing(jwt),
+            SettingError::Invalid(cipher, _),
+            SettingError::Missing(old_cipher)
+        ] if jwt == "TOKEN_JWT_KEY" && cipher == "TOKEN_CIPHER_KEY" && old_cipher == "TOKEN_CIPHER_KEY_OLD"));
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let token = "a token issued before the keys were rotated";
+        let old = Keyring::new("old", TokenKeys::new(&[1; 32], [2; 32]));
+        let encoded = old.encrypt(token, "test", Duration::minutes(5));
+
+        let rotated = Keyring::new("new", TokenKeys::new(&[3; 32], [4; 32]))
+            .with_retired("old", TokenKeys::new(&[1; 32], [2; 32]));
+        assert_eq!(token, rotated.decrypt(&encoded, "test").unwrap());
+        assert_eq!(token, rotated.decrypt(&rotated.encrypt(token, "test", Duration::minutes(5)), "test").unwrap());
+
+        let forgotten = Keyring::new("new", TokenKeys::new(&[3; 32], [4; 32]));
+        assert!(forgotten.decrypt(&encoded, "test").is_err());
+    }
+
+    #[test]
+    fn test_envelope_claims() {
+        let keyring = Keyring::new("0", TokenKeys::new(&[1; 32], [2; 32]));
+
+        let encoded = keyring.encrypt("token", "test", Duration::minutes(5));
+        assert!(keyring.decrypt(&encoded, "other-game").is_err());
+
+        let expired = keyring.encrypt("token", "test", Duration::seconds(-1));
+        assert!(keyring.decrypt(&expired, "test").is_err());
+
+        for malformed in ["", "a.b", "a.b.c", "not a token at all"] {
+            assert!(keyring.decrypt(malformed, "test").is_err());
+        }
+
+        let truncated = Token::new(Header { key_id: Some("0".to_string()), ..Default::default() }, TokenClaims {
+            v: 1,
+            iat: 0,
+            exp: i64::MAX,
+            game: "test".to_string(),
+            ct: "AAAA".to_string()
+        }).sign_with_key(&keyring.keys["0"].jwt).unwrap();
+        assert!(keyring.decrypt(truncated.as_str(), "test").is_err());
+    }
 }
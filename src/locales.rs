@@ -0,0 +1,150 @@
+use std::{collections::HashMap, fs, io, path::{Path, PathBuf}};
+
+use thiserror::Error;
+
+use crate::discord::ApplicationCommand;
+
+/// The locale every string must exist in, and what's used when a user's
+/// locale doesn't have a translation.
+pub const DEFAULT_LOCALE: &str = "en-US";
+
+#[derive(Error, Debug)]
+pub enum LocaleError {
+    #[error("failed to read {0:?}: {1}")]
+    Io(PathBuf, io::Error),
+    #[error("failed to parse locale {0:?}: {1}")]
+    Parse(PathBuf, serde_yml::Error),
+    #[error("there are no strings for the default locale, {DEFAULT_LOCALE}")]
+    MissingDefault
+}
+
+/// Translated strings, keyed by Discord locale code and then by string id.
+/// Strings may contain `{placeholders}`, filled in by [`Locales::format`].
+#[derive(Clone, Default, Debug)]
+pub struct Locales {
+    strings: HashMap<String, HashMap<String, String>>
+}
+
+impl Locales {
+    /// Loads every YAML file in `dir` as the strings for the locale it's named
+    /// after, like `en-US.yml`.
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, LocaleError> {
+        let dir = dir.as_ref();
+        let mut strings = HashMap::new();
+
+        for entry in fs::read_dir(dir).map_err(|e| LocaleError::Io(dir.to_path_buf(), e))?.filter_map(Result::ok) {
+            let path = entry.path();
+            if !entry.file_type().is_ok_and(|t| t.is_file()) {
+                continue;
+            }
+
+            let contents = fs::read_to_string(&path).map_err(|e| LocaleError::Io(path.clone(), e))?;
+            let locale = path.with_extension("").file_name().unwrap_or_default().to_string_lossy().into_owned();
+            strings.insert(locale, serde_yml::from_str(&contents).map_err(|e| LocaleError::Parse(path.clone(), e))?);
+        }
+
+        if !strings.contains_key(DEFAULT_LOCALE) {
+            return Err(LocaleError::MissingDefault);
+        }
+
+        Ok(Self { strings })
+    }
+
+    /// Looks up `key` in `locale`, then in its base language (`de` for
+    /// `de-AT`), then in the default locale. Unknown keys come back as is.
+    pub fn get<'a>(&'a self, locale: Option<&str>, key: &'a str) -> &'a str {
+        let base = locale.and_then(|locale| locale.split_once('-')).map(|(base, _)| base);
+
+        [locale, base, Some(DEFAULT_LOCALE)].into_iter()
+            .flatten()
+            .find_map(|locale| self.strings.get(locale)?.get(key))
+            .map(String::as_str)
+            .unwrap_or(key)
+    }
+
+    /// Looks up `key` like [`Locales::get`], replacing each `{name}` in it with
+    /// its value from `args`.
+    pub fn format(&self, locale: Option<&str>, key: &str, args: &[(&str, &str)]) -> String {
+        args.iter().fold(self.get(locale, key).to_string(), |s, (name, value)| s.replace(&format!("{{{name}}}"), value))
+    }
+
+    /// Every translation of `key` outside the default locale, or `None` if
+    /// there aren't any.
+    pub fn translations(&self, key: &str) -> Option<HashMap<String, String>> {
+        let translations: HashMap<_, _> = self.strings.iter()
+            .filter(|(locale, _)| *locale != DEFAULT_LOCALE)
+            .filter_map(|(locale, strings)| Some((locale.clone(), strings.get(key)?.clone())))
+            .collect();
+
+        (!translations.is_empty()).then_some(translations)
+    }
+
+    /// Fills in command and option descriptions and their localizations from
+    /// the `command.<name>.description` and
+    /// `command.<name>.option.<option>.description` strings, along with name
+    /// localizations from the matching `.name` strings.
+    pub fn localize_commands(&self, commands: &mut [ApplicationCommand]) {
+        let default = |key: &str| self.strings.get(DEFAULT_LOCALE).and_then(|strings| strings.get(key)).cloned();
+
+        for command in commands {
+            let prefix = format!("command.{}", command.name);
+            command.description = default(&format!("{prefix}.description")).unwrap_or(command.description.clone());
+            command.name_localizations = self.translations(&format!("{prefix}.name"));
+            command.description_localizations = self.translations(&format!("{prefix}.description"));
+
+            for option in &mut command.options {
+                let prefix = format!("{prefix}.option.{}", option.name);
+                option.description = default(&format!("{prefix}.description")).unwrap_or(option.description.clone());
+                option.name_localizations = self.translations(&format!("{prefix}.name"));
+                option.description_localizations = self.translations(&format!("{prefix}.description"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::discord::ApplicationCommand;
+
+    use super::Locales;
+
+    #[test]
+    fn test_fallback() {
+        let locales = Locales::from_dir("locales").unwrap();
+
+        assert_eq!("Link Test", locales.format(None, "link.button", &[("game", "Test")]));
+        assert_eq!("Test verknüpfen", locales.format(Some("de"), "link.button", &[("game", "Test")]));
+        assert_eq!("Test verknüpfen", locales.format(Some("de-AT"), "link.button", &[("game", "Test")]));
+        assert_eq!("Link Test", locales.format(Some("ja"), "link.button", &[("game", "Test")]));
+        assert_eq!("no.such.key", locales.get(Some("de"), "no.such.key"));
+    }
+
+    #[test]
+    fn test_every_string_is_translated() {
+        let locales = Locales::from_dir("locales").unwrap();
+        let default = &locales.strings[super::DEFAULT_LOCALE];
+
+        for (locale, strings) in &locales.strings {
+            let missing: Vec<_> = default.keys().filter(|key| !strings.contains_key(*key)).collect();
+            let unknown: Vec<_> = strings.keys().filter(|key| !default.contains_key(*key)).collect();
+            assert!(missing.is_empty() && unknown.is_empty(), "{locale} is missing {missing:?} and has unknown {unknown:?}");
+        }
+
+        assert_eq!(None::<HashMap<String, String>>, locales.translations("no.such.key"));
+    }
+
+    #[test]
+    fn test_localize_commands() {
+        let locales = Locales::from_dir("locales").unwrap();
+        let mut commands: Vec<ApplicationCommand> = serde_json::from_str(include_str!("../commands.json")).unwrap();
+        locales.localize_commands(&mut commands);
+
+        let link = commands.iter().find(|c| c.name == "link").unwrap();
+        assert_eq!("Get a link to connect your game profile", link.description);
+        assert_eq!(Some("Einen Link zum Verknüpfen deines Spielprofils erhalten"), link.description_localizations.as_ref().and_then(|l| l.get("de")).map(String::as_str));
+        assert_eq!(Some("Das zu verknüpfende Spiel"), link.options[0].description_localizations.as_ref().and_then(|l| l.get("de")).map(String::as_str));
+        assert_eq!(None, link.name_localizations);
+    }
+}
@@ -1,39 +1,20 @@
-use std::{collections::{BTreeMap, HashMap}, ops::{Range, RangeBounds}, fs, env};
+use std::{collections::{BTreeMap, HashMap}, sync::Arc};
 
-use lazy_static::lazy_static;
-use rocket::{get, serde::json::Json, routes, fs::{FileServer, NamedFile}, response::Redirect, http::{CookieJar, Cookie, Status}, State, futures::TryFutureExt, FromForm, post, form::Form};
+#[cfg(feature = "assets-hosting")]
+use rocket::fs::FileServer;
+use rocket::{get, serde::json::Json, routes, response::Redirect, Build, Rocket, http::{CookieJar, Cookie, Status}, State, FromForm, post, form::Form, fairing::AdHoc, request::{FromRequest, Outcome}, Request, http::uri::Origin, data::{ByteUnit, Data}};
 use rocket_dyn_templates::{Template, context};
 use serde::{Serialize, Deserialize};
-use soulfire::*;
-
-lazy_static! {
-    static ref GAMES: HashMap<String, (Game, GameInfo)> = load_games();
-}
+use soulfire::{*, discord::{DiscordApi, DiscordConfig, DiscordError}, locales::{LocaleError, Locales}, interactions::{verify_signature, CommandData, Interaction, InteractionResponse, InteractionType, LinkButton}, registry::{ConfigReport, GameCredentials, GameRegistry, RegisteredGame, SharedRegistry}, settings::Settings, store::{TokenStore, TokenStoreKind}, webhooks::{EventCounters, GameEventCounts, WebhookEvent, WebhookType}};
 
 #[cfg(not(feature = "testing"))]
-fn load_games() -> HashMap<String, (Game, GameInfo)> {
-    let mut map = HashMap::default();
-
-    for game in fs::read_dir("games").unwrap().filter_map(Result::ok) {
-        if game.file_type().unwrap().is_file() {
-            let contents = fs::read_to_string(game.path()).unwrap();
-            let yaml: Game = serde_yml::from_str(&contents).unwrap_or_else(|e| panic!("failed to parse game {:?}: {e}", game.path()));
-
-            let path = game.path().with_extension("");
-            let name = path.file_name().unwrap().to_string_lossy();
-            let info = GameInfo::from_suffix(&yaml.suffix);
-            map.insert(name.as_ref().to_owned(), (yaml, info));
-        }
-    }
-
-    map
+fn load_games(settings: &Settings) -> Result<SharedRegistry, ConfigReport> {
+    SharedRegistry::from_dir(settings.games_dir.clone().unwrap_or_else(|| "games".into()), settings)
 }
 
 #[cfg(feature = "testing")]
-fn load_games() -> HashMap<String, (Game, GameInfo)> {
-    let mut map = HashMap::default();
-
-    map.insert("test".to_string(), (Game {
+fn load_games(_settings: &Settings) -> Result<SharedRegistry, ConfigReport> {
+    Ok(SharedRegistry::new(GameRegistry::from_games([("test".to_string(), Game {
         name: "Hello World 2: Electric Boogalo".to_string(),
         main_page: Some("https://example.com".to_string()),
         suffix: "IRRELEVANT".to_string(),
@@ -44,52 +25,88 @@ fn load_games() -> HashMap<String, (Game, GameInfo)> {
             optional: false,
             max_length: 16
         },
-        keys: BTreeMap::default()
-    }, GameInfo::default()));
+        keys: BTreeMap::default(),
+        status: GameStatus::Active,
+        status_message: None,
+        aliases: Vec::new(),
+        application: None
+    }, GameCredentials::default())])))
+}
 
-    map
+fn load_locales(settings: &Settings) -> Result<Locales, LocaleError> {
+    Locales::from_dir(settings.locales_dir.clone().unwrap_or_else(|| "locales".into()))
 }
 
 struct BotInfo {
     domain: String,
-    #[cfg(not(feature = "testing"))]
-    client: reqwest::Client
-}
-
-#[cfg(not(feature = "testing"))]
-struct GameInfo {
-    application_id: u64,
-    client_id: u64,
-    client_secret: String,
+    keyring: Arc<Keyring>,
+    tokens: Box<dyn TokenStore>,
+    discord: Arc<dyn DiscordApi>,
+    discord_config: DiscordConfig,
+    admin_token: Option<String>,
+    locales: Locales,
+    events: EventCounters
 }
 
-#[cfg(feature = "testing")]
-#[derive(Default)]
-struct GameInfo;
-
-#[cfg(not(feature = "testing"))]
-impl GameInfo {
-    pub fn from_suffix(suffix: &str) -> Self {
-        Self {
-            application_id: u64::from_str_radix(&env::var(format!("APP_ID_{suffix}")).unwrap(), 10).unwrap(),
-            client_id: u64::from_str_radix(&env::var(format!("CLIENT_ID_{suffix}")).unwrap(), 10).unwrap(),
-            client_secret: env::var(format!("CLIENT_SECRET_{suffix}")).unwrap(),
+#[rocket::launch]
+fn launch() -> _ {
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("invalid settings: {e}");
+            std::process::exit(1);
         }
-    }
+    };
+
+    let mut errors = Vec::new();
+    let games = load_games(&settings).map_err(|report| errors.extend(report.0)).ok();
+    let locales = load_locales(&settings).map_err(|e| errors.push(e.into())).ok();
+    let keyring = Keyring::from_settings(&settings).map_err(|e| errors.extend(e.into_iter().map(Into::into))).ok();
+    let store = TokenStoreKind::from_settings(&settings).map_err(|e| errors.push(e.into())).ok();
+    let discord_config = DiscordConfig::from_settings(&settings).map_err(|e| errors.push(e.into())).ok();
+
+    let (Some(games), Some(locales), Some(keyring), Some(store), Some(discord_config)) = (games, locales, keyring, store, discord_config) else {
+        eprintln!("{}", ConfigReport(errors));
+        std::process::exit(1);
+    };
+    let keyring = Arc::new(keyring);
+
+    rocket(BotInfo {
+        domain: settings.domain().to_string(),
+        tokens: store.open(keyring.clone()),
+        keyring,
+        #[cfg(not(feature = "testing"))]
+        discord: Arc::new(soulfire::discord::ReqwestDiscordApi::new(reqwest::Client::default(), &discord_config)),
+        #[cfg(feature = "testing")]
+        discord: Arc::new(soulfire::discord::MockDiscordApi::default()),
+        discord_config,
+        admin_token: settings.admin_token.clone().filter(|token| !token.is_empty()),
+        locales,
+        events: EventCounters::default()
+    }, games)
 }
 
-#[rocket::launch]
-fn launch() -> _ {
+fn rocket(bot: BotInfo, games: SharedRegistry) -> Rocket<Build> {
     #[allow(unused_mut)]
     let mut rk = rocket::build()
         .attach(Template::fairing())
-        .manage(BotInfo {
-            domain: env::var("DOMAIN").unwrap_or_else(|_| "soulfire.derfrühling.net".to_string()),
-            #[cfg(not(feature = "testing"))]
-            client: reqwest::Client::builder()
-                .build().unwrap()
-        })
-        .mount("/", routes![get_game, get_game_link_page, set_game_link_status, get_link_success, link_discord, add_bot, get_all_games]);
+        .attach(AdHoc::on_liftoff("Reload games on SIGHUP", |rocket| Box::pin(async move {
+            #[cfg(unix)] {
+                let games = rocket.state::<SharedRegistry>().unwrap().clone();
+
+                tokio::spawn(async move {
+                    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).unwrap();
+
+                    while hangup.recv().await.is_some() {
+                        log::info!("Received SIGHUP, reloading games");
+                        games.reload();
+                    }
+                });
+            }
+        })))
+        .manage(bot)
+        .manage(games)
+        .mount("/", routes![get_game, get_game_link_page, set_game_link_status, reapply_game_link_status, unlink_game, get_link_success, link_discord, add_bot, get_all_games, handle_interaction, handle_webhook_event, reload_games, get_event_counts]);
 
     #[cfg(feature = "assets-hosting")] {
         rk = rk.mount("/assets", FileServer::from("assets/"));
@@ -99,39 +116,88 @@ fn launch() -> _ {
 }
 
 #[derive(rocket::Responder)]
+#[allow(clippy::enum_variant_names)]
 enum Error {
     #[response(status = 404)]
     NotFound(&'static str),
     #[response(status = 400)]
     BadRequest(&'static str),
+    #[response(status = 401)]
+    Unauthorized(&'static str),
+    #[response(status = 413)]
+    PayloadTooLarge(&'static str),
     #[response(status = 500)]
     InternalServerError(&'static str),
+    Unavailable((Status, String)),
+    Moved(Box<Redirect>),
+    Reauthorize(Box<Redirect>),
     DiscordPassed((Status, String))
 }
 
+/// What to answer for a game id that isn't registered: a redirect to the same
+/// page under the game's current id if it's an alias, or a 404 otherwise.
+fn missing_game(games: &SharedRegistry, id: &str, uri: &Origin<'_>) -> Error {
+    let games = games.current();
+    let Some(canonical) = games.canonical_id(id) else {
+        return Error::NotFound("The requested game was not found.");
+    };
+
+    let prefix = format!("/games/{id}");
+    let rest = uri.path().as_str().strip_prefix(&prefix).unwrap_or_default();
+    let query = uri.query().map(|q| format!("?{q}")).unwrap_or_default();
+    Error::Moved(Box::new(Redirect::permanent(format!("/games/{canonical}{rest}{query}"))))
+}
+
+/// Explains why a game isn't taking new links, if it isn't.
+fn status_notice(game: &Game) -> Option<String> {
+    let default = match game.status {
+        GameStatus::Active => return None,
+        GameStatus::Maintenance => "This game is under maintenance. Please come back later!",
+        GameStatus::Retired => "This game has been retired. You can still remove your linked role information."
+    };
+
+    Some(game.status_message.clone().unwrap_or_else(|| default.to_string()))
+}
+
+/// Fails with the game's notice if it's under maintenance, or if it's retired
+/// and `allow_retired` isn't set.
+fn ensure_linkable(game: &Game, allow_retired: bool) -> Result<(), Error> {
+    match (game.status, status_notice(game)) {
+        (GameStatus::Maintenance, Some(notice)) => Err(Error::Unavailable((Status::ServiceUnavailable, notice))),
+        (GameStatus::Retired, Some(notice)) if !allow_retired => Err(Error::Unavailable((Status::Gone, notice))),
+        _ => Ok(())
+    }
+}
+
 #[get("/games/<game>")]
-fn get_game(game: &str) -> Result<Json<Game>, Error> {
-    match GAMES.get(game) {
-        Some((v, _)) => Ok(Json(v.clone())),
+fn get_game(game: &str, games: &State<SharedRegistry>) -> Result<Json<Game>, Error> {
+    let games = games.current();
+
+    match games.get(games.canonical_id(game).unwrap_or(game)) {
+        Some(RegisteredGame { game: v, .. }) => Ok(Json(v.clone())),
         None => Err(Error::NotFound("The requested game was not found.")),
     }
 }
 
 #[get("/games/<game>/link?<hpn>")]
-fn get_game_link_page(game: &str, hpn: Option<bool>, bot: &State<BotInfo>) -> Result<Template, Error> {
-    match GAMES.get(game) {
-        Some((v, _)) => Ok(Template::render("entry", context! {
+fn get_game_link_page(game: &str, hpn: Option<bool>, jar: &CookieJar<'_>, bot: &State<BotInfo>, games: &State<SharedRegistry>, uri: &Origin<'_>) -> Result<Template, Error> {
+    match games.current().get(game) {
+        Some(RegisteredGame { game: v, credentials: info }) => Ok(Template::render("entry", context! {
             id: game,
-            domain: &bot.domain,
+            authorize_url: discord_authorize_url(bot, game, info),
             name: &v.name,
             uid_max_length: v.uid.max_length,
             username: context! {
                 is_optional: v.username.optional,
                 max_length: v.username.max_length
             },
+            saved: load_link_details(bot, jar, game),
+            notice: status_notice(v),
+            linkable: v.status == GameStatus::Active,
+            unlinkable: v.status != GameStatus::Maintenance,
             hide_privacy_notice: hpn.unwrap_or_default()
         })),
-        None => Err(Error::NotFound("The requested game was not found.")),
+        None => Err(missing_game(games, game, uri)),
     }
 }
 
@@ -141,138 +207,446 @@ struct GameLinkStatus {
     username: String
 }
 
-#[post("/games/<game>/link", data = "<data>")]
-#[cfg(not(feature = "testing"))]
-async fn set_game_link_status(game: &str, data: Form<GameLinkStatus>, jar: &CookieJar<'_>, bot: &State<BotInfo>) -> Result<Redirect, Error> {
-    match GAMES.get(game) {
-        Some((v, info)) => {
-            let cookie = jar.get("dstk").ok_or_else(|| Error::BadRequest("No token acquired."))?;
-            let token = decrypt_key(cookie.value()).map_err(|_| Error::BadRequest("Invalid token"))?;
-
-            let res = bot.client
-                .put(format!("https://discord.com/api/v10/users/@me/applications/{}/role-connection", info.application_id))
-                .body(serde_json::to_string(&v.make_role_connection_info(data.uid, &data.username))
-                    .map_err(|_| Error::InternalServerError("Internal server error. Oops!"))?)
-                .header("Content-Type", "application/json")
-                .header("User-Agent", "DiscordBot (https://github.com/der-fruhling)")
-                .header("Authorization", format!("Bearer {}", token))
-                .send().await.map_err(|e| {
-                    log::error!("error interacting with Discord to change role connection info: {e}");
-                    Error::InternalServerError("Internal server error. Oops!")
-                })?;
+/// The last details a user submitted for a game, kept encrypted in their own
+/// browser so they can re-apply them later without the server storing anything.
+#[derive(Serialize, Deserialize)]
+struct SavedLinkDetails {
+    uid: u64,
+    username: String
+}
 
-            if !res.status().is_success() {
-                log::error!("Failed to set role connection: {} {:?}", res.status(), res.text().await);
-                return Err(Error::InternalServerError("Failed to set your role connection.\n-> That's an internal server error. Oops!"));
-            }
+fn load_link_details(bot: &BotInfo, jar: &CookieJar<'_>, game: &str) -> Option<SavedLinkDetails> {
+    let cookie = jar.get("dsud")?;
+    let json = bot.keyring.decrypt(cookie.value(), game).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+fn save_link_details(bot: &BotInfo, jar: &CookieJar<'_>, game: &str, details: &SavedLinkDetails) -> Result<(), Error> {
+    let json = serde_json::to_string(details)
+        .map_err(|_| Error::InternalServerError("Internal server error. Oops!"))?;
+
+    jar.add(Cookie::build(("dsud", bot.keyring.encrypt(&json, game, time::Duration::days(365))))
+        .path(format!("/games/{game}"))
+        .secure(true)
+        .http_only(true)
+        .expires(cookie::Expiration::DateTime(time::OffsetDateTime::now_utc() + time::Duration::days(365)))
+        .same_site(cookie::SameSite::Strict));
+
+    Ok(())
+}
+
+/// Where to send a user to (re-)authorize Soulfire with Discord for a game.
+fn discord_authorize_url(bot: &BotInfo, game: &str, info: &GameCredentials) -> String {
+    format!(
+        "{}?client_id={}&response_type=code&redirect_uri={}&scope=role_connections.write",
+        bot.discord_config.authorize_url(),
+        info.client_id,
+        urlencoding::encode(&format!("https://{}/games/{game}/discord-auth-flow", bot.domain))
+    )
+}
 
-            jar.remove("dstk");
-            Ok(Redirect::to(format!("/success")))
+/// Pushes the user's role connection with the token in their cookie. If the
+/// token is gone, expired, or revoked on Discord's side, the cookie is dropped
+/// and the user is sent back to authorize again.
+async fn push_role_connection(id: &str, info: &GameCredentials, jar: &CookieJar<'_>, bot: &BotInfo, connection: &PutRoleConnectionInfo<'_>) -> Result<(), Error> {
+    let reauthorize = || {
+        jar.remove("dstk");
+        Error::Reauthorize(Box::new(Redirect::to(discord_authorize_url(bot, id, info))))
+    };
+
+    let cookie = jar.get("dstk").ok_or_else(reauthorize)?;
+    let token = bot.tokens.redeem(cookie.value(), id).map_err(|_| reauthorize())?;
+
+    bot.discord.put_role_connection(info.application_id, &token, connection).await.map_err(|e| match e {
+        DiscordError::Status { status: 401 | 403, .. } => {
+            log::info!("Token was rejected while setting role connection, asking to authorize again: {e}");
+            bot.tokens.revoke(cookie.value());
+            reauthorize()
         },
-        None => Err(Error::NotFound("The requested game was not found.")),
-    }
+        DiscordError::RateLimited(_) | DiscordError::Status { status: 429, .. } => {
+            log::warn!("Gave up setting role connection: {e}");
+            Error::Unavailable((Status::ServiceUnavailable, "Discord is busy right now. Please try again in a minute.".to_string()))
+        },
+        e => {
+            log::error!("Failed to set role connection: {e}");
+            Error::InternalServerError("Failed to set your role connection.\n-> That's an internal server error. Oops!")
+        }
+    })?;
+
+    bot.tokens.revoke(cookie.value());
+    jar.remove("dstk");
+    Ok(())
 }
 
 #[post("/games/<game>/link", data = "<data>")]
-#[cfg(feature = "testing")]
-async fn set_game_link_status(game: &str, data: Form<GameLinkStatus>, jar: &CookieJar<'_>, bot: &State<BotInfo>) -> Result<Redirect, Error> {
-    Err(Error::BadRequest("not implemented in testing versions"))
+async fn set_game_link_status(game: &str, data: Form<GameLinkStatus>, jar: &CookieJar<'_>, bot: &State<BotInfo>, games: &State<SharedRegistry>, uri: &Origin<'_>) -> Result<Redirect, Error> {
+    match games.current().get(game) {
+        Some(RegisteredGame { game: v, credentials: info }) => {
+            ensure_linkable(v, false)?;
+
+            // saved first, so the input survives being sent off to authorize again
+            save_link_details(bot, jar, game, &SavedLinkDetails { uid: data.uid, username: data.username.clone() })?;
+            push_role_connection(game, info, jar, bot, &v.make_role_connection_info(data.uid, &data.username)).await?;
+            Ok(Redirect::to("/success"))
+        },
+        None => Err(missing_game(games, game, uri)),
+    }
 }
 
-#[get("/success")]
-fn get_link_success() -> Template {
-    Template::render("success", ())
+/// Recomputes a user's role connection from their saved details against the
+/// game's current keys, so changed key definitions don't require re-entering anything.
+#[post("/games/<game>/reapply")]
+async fn reapply_game_link_status(game: &str, jar: &CookieJar<'_>, bot: &State<BotInfo>, games: &State<SharedRegistry>, uri: &Origin<'_>) -> Result<Redirect, Error> {
+    match games.current().get(game) {
+        Some(RegisteredGame { game: v, credentials: info }) => {
+            ensure_linkable(v, false)?;
+
+            let saved = load_link_details(bot, jar, game).ok_or(Error::BadRequest("No saved details to re-apply."))?;
+            push_role_connection(game, info, jar, bot, &v.make_role_connection_info(saved.uid, &saved.username)).await?;
+            Ok(Redirect::to("/success"))
+        },
+        None => Err(missing_game(games, game, uri)),
+    }
 }
 
-#[derive(Deserialize)]
-struct TokenReturn<'a> {
-    #[serde(borrow)] access_token: &'a str,
-    expires_in: usize,
-    #[serde(borrow)] scope: &'a str
+/// Clears a user's role connection and forgets their saved details. This
+/// keeps working once a game is retired, so nobody is left stuck with a role.
+#[post("/games/<game>/unlink")]
+async fn unlink_game(game: &str, jar: &CookieJar<'_>, bot: &State<BotInfo>, games: &State<SharedRegistry>, uri: &Origin<'_>) -> Result<Redirect, Error> {
+    match games.current().get(game) {
+        Some(RegisteredGame { game: v, credentials: info }) => {
+            ensure_linkable(v, true)?;
+
+            push_role_connection(game, info, jar, bot, &v.make_unlink_info()).await?;
+            jar.remove(Cookie::build("dsud").path(format!("/games/{game}")));
+            Ok(Redirect::to("/success?unlinked"))
+        },
+        None => Err(missing_game(games, game, uri)),
+    }
+}
+
+#[get("/success?<unlinked>")]
+fn get_link_success(unlinked: Option<bool>) -> Template {
+    Template::render("success", context! {
+        unlinked: unlinked.unwrap_or_default()
+    })
 }
 
 #[get("/games/<game>/discord-auth-flow?<code>")]
-#[cfg(not(feature = "testing"))]
-async fn link_discord(game: &str, code: &str, jar: &CookieJar<'_>, bot: &State<BotInfo>) -> Result<Redirect, Error> {
-    match GAMES.get(game) {
-        Some((v, info)) => {
+async fn link_discord(game: &str, code: &str, jar: &CookieJar<'_>, bot: &State<BotInfo>, games: &State<SharedRegistry>, uri: &Origin<'_>) -> Result<Redirect, Error> {
+    match games.current().get(game) {
+        Some(RegisteredGame { credentials: info, .. }) => {
             if code.chars().any(|c| !c.is_alphanumeric()) {
                 return Err(Error::BadRequest("Bad request."));
             }
 
-            let contents = format!(
-                "grant_type=authorization_code&code={code}&redirect_uri={}",
-                urlencoding::encode(&format!("https://{}/games/{game}/discord-auth-flow", bot.domain))
-            );
-
-            let res = bot.client.post("https://discord.com/api/v10/oauth2/token")
-                .body(contents)
-                .header("Content-Type", "application/x-www-form-urlencoded")
-                .header("User-Agent", "DiscordBot (https://github.com/der-fruhling)")
-                .basic_auth(info.client_id, Some(&info.client_secret))
-                .send().await.map_err(|e| {
-                    log::error!("error interacting with Discord for an auth token: {e:?}");
+            let redirect_uri = format!("https://{}/games/{game}/discord-auth-flow", bot.domain);
+            let token_data = bot.discord.exchange_code(info.client_id, &info.client_secret, code, &redirect_uri).await.map_err(|e| match e {
+                DiscordError::Status { status, .. } => Error::DiscordPassed((Status::from_code(status).unwrap_or(Status::InternalServerError), format!("Discord-passed internal error. {status}"))),
+                e => {
+                    log::error!("error interacting with Discord for an auth token: {e}");
                     Error::InternalServerError("Internal server error. Oops!")
-                })?;
-
-            if !res.status().is_success() {
-                return Err(Error::DiscordPassed((Status::from_code(res.status().into()).unwrap(), format!("Discord-passed internal error. {}", res.status()))));
-            }
-
-            let token_data = res.text().await
-                .unwrap_or_else(|_| panic!("successful auth interaction with Discord; no body????"));
-            let token_data: TokenReturn = serde_json::from_str(&token_data)
-                .map_err(|_| Error::InternalServerError("Internal server error. Oops!"))?;
+                }
+            })?;
 
             if token_data.scope != "role_connections.write" {
                 return Err(Error::BadRequest("Invalid operation"));
             }
 
-            jar.add(Cookie::build(("dstk", generate_encrypted_key(token_data.access_token)))
+            let expires_in = time::Duration::seconds(token_data.expires_in.saturating_sub(100) as i64);
+            jar.add(Cookie::build(("dstk", bot.tokens.issue(&token_data.access_token, game, expires_in)))
                 .secure(true)
-                .expires(cookie::Expiration::DateTime(time::OffsetDateTime::now_utc() + time::Duration::seconds((token_data.expires_in - 100) as i64)))
+                .expires(cookie::Expiration::DateTime(time::OffsetDateTime::now_utc() + expires_in))
                 .same_site(cookie::SameSite::Strict));
 
             Ok(Redirect::to(format!("/games/{game}/link?hpn")))
         },
-        None => Err(Error::NotFound("The requested game was not found.")),
+        None => Err(missing_game(games, game, uri)),
     }
 }
 
-#[get("/games/<game>/discord-auth-flow?<code>")]
-#[cfg(feature = "testing")]
-async fn link_discord(game: &str, code: &str) -> Result<Redirect, Error> {
-    Err(Error::BadRequest("not implemented in testing versions"))
-}
-
 #[get("/games/<game>/add-bot")]
-async fn add_bot(game: &str) -> Result<Template, Error> {
-    match GAMES.get(game) {
-        Some((v, info)) => {
+async fn add_bot(game: &str, bot: &State<BotInfo>, games: &State<SharedRegistry>, uri: &Origin<'_>) -> Result<Template, Error> {
+    match games.current().get(game) {
+        Some(RegisteredGame { game: v, credentials: info }) => {
             Ok(Template::render("add-bot", context! {
                 name: &v.name,
-                auth: (|| {
-                    #[cfg(not(feature = "testing"))] {
-                        return format!("https://discord.com/oauth2/authorize?client_id={}&permissions=0&scope=bot", info.client_id)
-                    }
-
-                    "https://example.com".to_string()
-                })()
+                auth: format!("{}?client_id={}&permissions=0&scope=bot", bot.discord_config.authorize_url(), info.client_id)
             }))
         },
-        None => Err(Error::NotFound("The requested game was not found.")),
+        None => Err(missing_game(games, game, uri)),
     }
 }
 
 #[get("/all-games")]
-async fn get_all_games() -> Result<Template, Error> {
+async fn get_all_games(games: &State<SharedRegistry>) -> Result<Template, Error> {
     Ok(Template::render("all-games", context! {
-        games: GAMES.iter()
-            .map(|(id, (game, _))| context! {
+        games: games.current().iter()
+            .map(|(id, RegisteredGame { game, .. })| context! {
                 name: &game.name,
                 id: id,
-                main_page: game.main_page.as_ref()
+                main_page: game.main_page.as_ref(),
+                status: match game.status {
+                    GameStatus::Active => None,
+                    GameStatus::Maintenance => Some("Maintenance"),
+                    GameStatus::Retired => Some("Retired")
+                }
             })
             .collect::<Vec<_>>()
     }))
 }
+
+/// The signature Discord sends with every interaction and webhook event.
+struct DiscordSignature<'r> {
+    signature: &'r str,
+    timestamp: &'r str
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DiscordSignature<'r> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match (req.headers().get_one("X-Signature-Ed25519"), req.headers().get_one("X-Signature-Timestamp")) {
+            (Some(signature), Some(timestamp)) => Outcome::Success(DiscordSignature { signature, timestamp }),
+            _ => Outcome::Error((Status::Unauthorized, ()))
+        }
+    }
+}
+
+/// The most Soulfire reads of a request from Discord. Interactions and
+/// webhook events are well past Rocket's 8 KiB default for strings once they
+/// carry resolved users and members.
+const DISCORD_BODY_LIMIT: ByteUnit = ByteUnit::Mebibyte(1);
+
+async fn read_discord_body(body: Data<'_>) -> Result<String, Error> {
+    let body = body.open(DISCORD_BODY_LIMIT).into_string().await.map_err(|_| Error::BadRequest("Bad request."))?;
+
+    if !body.is_complete() {
+        return Err(Error::PayloadTooLarge("Request body too large."));
+    }
+
+    Ok(body.into_inner())
+}
+
+/// Receives the interactions Discord sends to a game's app. Set the app's
+/// interactions endpoint URL to this, and its public key in `PUBLIC_KEY_<SUFFIX>`.
+#[post("/games/<game>/interactions", data = "<body>")]
+async fn handle_interaction(game: &str, signature: DiscordSignature<'_>, body: Data<'_>, bot: &State<BotInfo>, games: &State<SharedRegistry>) -> Result<Json<InteractionResponse>, Error> {
+    let body = read_discord_body(body).await?;
+    let games = games.current();
+    let Some(RegisteredGame { credentials: info, .. }) = games.get(games.canonical_id(game).unwrap_or(game)) else {
+        return Err(Error::NotFound("The requested game was not found."));
+    };
+
+    let key = info.public_key.as_ref().ok_or(Error::NotFound("Interactions aren't set up for this game."))?;
+    if !verify_signature(key, signature.signature, signature.timestamp, &body) {
+        return Err(Error::Unauthorized("Invalid request signature."));
+    }
+
+    let interaction: Interaction = serde_json::from_str(&body).map_err(|_| Error::BadRequest("Bad request."))?;
+
+    let locale = interaction.locale.as_deref();
+    let guild_locale = interaction.guild_locale.as_deref().or(locale);
+
+    let response = match (interaction.ty, interaction.data.as_ref()) {
+        (InteractionType::Ping, _) => InteractionResponse::pong(),
+        (InteractionType::ApplicationCommand, Some(data)) => match data.name.as_str() {
+            "help" => help_message(bot, &games, info.application_id, locale, true),
+            // everyone in the channel sees this one, so it's in the server's language
+            "post-help" => help_message(bot, &games, info.application_id, guild_locale, false),
+            "link" => link_message(bot, &games, info.application_id, locale, data),
+            "status" => status_message(bot, &games, info.application_id, locale, data),
+            _ => return Err(Error::BadRequest("Unknown command."))
+        },
+        (InteractionType::ApplicationCommandAutocomplete, Some(data)) if data.name == "link" || data.name == "status" => {
+            let query = data.option("game").unwrap_or_default().to_lowercase();
+
+            InteractionResponse::autocomplete(linkable_games(&games, info.application_id)
+                .filter(|(id, RegisteredGame { game, .. })| id.contains(&query) || game.name.to_lowercase().contains(&query))
+                .map(|(id, RegisteredGame { game, .. })| (game.name.clone(), id.to_string())))
+        },
+        _ => return Err(Error::BadRequest("Unsupported interaction."))
+    };
+
+    Ok(Json(response))
+}
+
+/// The games an application can currently link, which is usually just one.
+fn linkable_games(games: &GameRegistry, application_id: u64) -> impl Iterator<Item = (&str, &RegisteredGame)> {
+    games.iter().filter(move |(_, RegisteredGame { game, credentials })| credentials.application_id == application_id && game.status == GameStatus::Active)
+}
+
+fn link_button(bot: &BotInfo, locale: Option<&str>, id: &str, game: &Game) -> LinkButton {
+    LinkButton {
+        label: bot.locales.format(locale, "link.button", &[("game", &game.name)]),
+        url: format!("https://{}/games/{id}/link", bot.domain)
+    }
+}
+
+/// Explains how to get roles, with a button to link each active game this
+/// application serves.
+fn help_message(bot: &BotInfo, games: &GameRegistry, application_id: u64, locale: Option<&str>, ephemeral: bool) -> InteractionResponse {
+    let buttons = linkable_games(games, application_id)
+        .map(|(id, RegisteredGame { game, .. })| link_button(bot, locale, id, game))
+        .collect();
+
+    InteractionResponse::message(bot.locales.get(locale, "help.content"), ephemeral, buttons)
+}
+
+/// The game a command is about: the one picked in its `game` option, or
+/// the only game the application serves when none was picked. Fails with
+/// the id of the string explaining why there isn't one.
+fn chosen_game<'g>(games: &'g GameRegistry, application_id: u64, data: &CommandData) -> Result<(&'g str, &'g Game), &'static str> {
+    let choices: Vec<_> = linkable_games(games, application_id).collect();
+    let chosen = match data.option("game") {
+        Some(choice) => choices.iter().find(|(id, RegisteredGame { game, .. })| *id == choice || game.aliases.iter().any(|a| a == choice)),
+        None if choices.len() == 1 => choices.first(),
+        None => return Err("game.ambiguous")
+    };
+
+    chosen.map(|(id, RegisteredGame { game, .. })| (*id, game))
+        .ok_or("game.unavailable")
+}
+
+/// Replies to `/link` with a button for the chosen game.
+fn link_message(bot: &BotInfo, games: &GameRegistry, application_id: u64, locale: Option<&str>, data: &CommandData) -> InteractionResponse {
+    match chosen_game(games, application_id, data) {
+        Ok((id, game)) => InteractionResponse::message(
+            bot.locales.format(locale, "link.content", &[("game", &game.name)]),
+            true,
+            vec![link_button(bot, locale, id, game)]
+        ),
+        Err(problem) => InteractionResponse::message(bot.locales.get(locale, problem), true, Vec::new())
+    }
+}
+
+/// Replies to `/status`. Discord only shares a user's role connection with
+/// their own OAuth login, which an interaction doesn't carry, so this can't
+/// say what's currently linked. Given a UID, it shows which keys that UID
+/// would meet instead, which answers most "why don't I have the role".
+fn status_message(bot: &BotInfo, games: &GameRegistry, application_id: u64, locale: Option<&str>, data: &CommandData) -> InteractionResponse {
+    let (id, game) = match chosen_game(games, application_id, data) {
+        Ok(chosen) => chosen,
+        Err(problem) => return InteractionResponse::message(bot.locales.get(locale, problem), true, Vec::new())
+    };
+
+    let mut content = bot.locales.format(locale, "status.content", &[("game", &game.name)]);
+    content += "\n\n";
+
+    match data.integer_option("uid") {
+        Some(uid) if uid.to_string().len() > game.uid.max_length => {
+            content += &bot.locales.format(locale, "status.uid_too_long", &[("uid", &uid.to_string()), ("game", &game.name)]);
+        },
+        Some(uid) => {
+            content += &bot.locales.format(locale, "status.preview", &[("uid", &uid.to_string())]);
+
+            for key in game.keys.values() {
+                let translated = |default: &str, translations: &Option<HashMap<String, String>>| locale
+                    .and_then(|locale| translations.as_ref()?.get(locale).cloned())
+                    .unwrap_or_else(|| default.to_string());
+                let mark = if key.matches(uid) { "✅" } else { "❌" };
+
+                content += &format!(
+                    "\n{mark} **{}**: {}",
+                    translated(&key.name, &key.name_localizations),
+                    translated(&key.description, &key.description_localizations)
+                );
+            }
+        },
+        None => content += bot.locales.get(locale, "status.no_uid")
+    }
+
+    InteractionResponse::message(content, true, vec![link_button(bot, locale, id, game)])
+}
+
+/// Receives the webhook events Discord sends when a game's app is added to or
+/// removed from a server or account. Set the app's webhook events URL to this,
+/// and subscribe it to `APPLICATION_AUTHORIZED` and `APPLICATION_DEAUTHORIZED`.
+///
+/// Nothing is revoked on deauthorization, because Soulfire never keeps a
+/// refresh token: [`AccessToken`](soulfire::discord::AccessToken) drops the
+/// one Discord hands out with each access token. The short-lived access
+/// tokens a store does hold are invalidated by Discord along with the
+/// authorization, and dropped the next time Discord refuses one.
+#[post("/games/<game>/webhook-events", data = "<body>")]
+async fn handle_webhook_event(game: &str, signature: DiscordSignature<'_>, body: Data<'_>, bot: &State<BotInfo>, games: &State<SharedRegistry>) -> Result<Status, Error> {
+    let body = read_discord_body(body).await?;
+    let games = games.current();
+    let game = games.canonical_id(game).unwrap_or(game);
+    let Some(RegisteredGame { credentials: info, .. }) = games.get(game) else {
+        return Err(Error::NotFound("The requested game was not found."));
+    };
+
+    let key = info.public_key.as_ref().ok_or(Error::NotFound("Webhook events aren't set up for this game."))?;
+    if !verify_signature(key, signature.signature, signature.timestamp, &body) {
+        return Err(Error::Unauthorized("Invalid request signature."));
+    }
+
+    let webhook: WebhookEvent = serde_json::from_str(&body).map_err(|_| Error::BadRequest("Bad request."))?;
+
+    if let (WebhookType::Event, Some(event)) = (webhook.ty, webhook.event.as_ref()) {
+        if bot.events.record(game, event) {
+            let integration = match event.data.integration_type {
+                Some(0) => "guild",
+                Some(1) => "user",
+                _ => "-"
+            };
+
+            log::info!("event={} game={} integration={} guild={} user={}", event.ty, game, integration,
+                event.data.guild.as_ref().map_or("-", |guild| guild.id.as_str()), event.data.user.as_ref().map_or("-", |user| user.id.as_str()));
+        } else {
+            log::debug!("event={} game={} ignored", event.ty, game);
+        }
+    }
+
+    Ok(Status::NoContent)
+}
+
+/// Guards routes that are only for whoever runs this instance, who must
+/// send `Authorization: Bearer <ADMIN_TOKEN>`. Without an `ADMIN_TOKEN`,
+/// nobody gets in.
+struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let expected = req.rocket().state::<BotInfo>().and_then(|bot| bot.admin_token.as_deref());
+        let given = req.headers().get_one("Authorization").and_then(|h| h.strip_prefix("Bearer "));
+
+        match (expected, given) {
+            (Some(expected), Some(given)) if constant_time_eq(expected.as_bytes(), given.as_bytes()) => Outcome::Success(Admin),
+            _ => Outcome::Error((Status::Unauthorized, ()))
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[get("/admin/events")]
+fn get_event_counts(_admin: Admin, bot: &State<BotInfo>) -> Json<BTreeMap<String, GameEventCounts>> {
+    Json(bot.events.snapshot())
+}
+
+#[derive(Serialize)]
+struct ReloadReport {
+    games: usize,
+    errors: Vec<String>
+}
+
+#[post("/admin/reload")]
+fn reload_games(_admin: Admin, games: &State<SharedRegistry>) -> Json<ReloadReport> {
+    let errors = games.reload();
+
+    Json(ReloadReport {
+        games: games.current().len(),
+        errors: errors.iter().map(ToString::to_string).collect()
+    })
+}
+
+#[cfg(test)]
+mod tests;
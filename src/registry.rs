@@ -0,0 +1,589 @@
+use std::{collections::BTreeMap, fmt, fs, io, path::{Path, PathBuf}, sync::{Arc, RwLock}};
+
+use ed25519_dalek::VerifyingKey;
+use thiserror::Error;
+
+use crate::{locales::LocaleError, settings::{SettingError, Settings}, Game};
+
+#[derive(Error, Debug)]
+pub enum GameLoadError {
+    #[error("failed to read {0:?}: {1}")]
+    Io(PathBuf, io::Error),
+    #[error("failed to parse game {0:?}: {1}")]
+    Parse(PathBuf, serde_yml::Error),
+    #[error("invalid game {0:?}: {1}")]
+    Invalid(PathBuf, String),
+    #[error("games {0:?} and {1:?} both use the suffix {2}")]
+    DuplicateSuffix(String, String, String),
+    #[error("game {0:?} can't use the alias {1:?}, it's already taken by {2:?}")]
+    DuplicateAlias(String, String, String),
+    #[error(transparent)]
+    Setting(#[from] SettingError),
+    #[error(transparent)]
+    Locales(#[from] LocaleError)
+}
+
+/// Every problem found while loading games and the rest of the config at
+/// startup, so they can all be fixed in one go.
+#[derive(Error, Debug)]
+pub struct ConfigReport(pub Vec<GameLoadError>);
+
+impl fmt::Display for ConfigReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "found {} problem(s) with the config:", self.0.len())?;
+
+        for e in &self.0 {
+            write!(f, "\n  - {e}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Discord's limits on role connection metadata.
+const MAX_KEYS: usize = 5;
+const MAX_KEY_LENGTH: usize = 50;
+const MAX_KEY_NAME_LENGTH: usize = 100;
+const MAX_KEY_DESCRIPTION_LENGTH: usize = 200;
+/// Discord's limits on an application's profile.
+const MAX_DESCRIPTION_LENGTH: usize = 400;
+const MAX_TAGS: usize = 5;
+const MAX_TAG_LENGTH: usize = 20;
+
+/// Checks a game against what Discord will accept, returning every problem.
+fn validate(game: &Game) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if game.suffix.is_empty() || !game.suffix.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') {
+        problems.push(format!("suffix {:?} must be made of A-Z, 0-9 and _", game.suffix));
+    }
+
+    for alias in &game.aliases {
+        if alias.is_empty() || !alias.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_') {
+            problems.push(format!("alias {alias:?} must be made of a-z, 0-9, - and _"));
+        }
+    }
+
+    if game.keys.len() > MAX_KEYS {
+        problems.push(format!("has {} keys, Discord allows at most {MAX_KEYS}", game.keys.len()));
+    }
+
+    for (key, value) in &game.keys {
+        if key.is_empty() || key.len() > MAX_KEY_LENGTH || !key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+            problems.push(format!("key {key:?} must be 1-{MAX_KEY_LENGTH} characters of a-z, 0-9 and _"));
+        }
+
+        if value.name.is_empty() || value.name.chars().count() > MAX_KEY_NAME_LENGTH {
+            problems.push(format!("key {key:?} must have a name of 1-{MAX_KEY_NAME_LENGTH} characters"));
+        }
+
+        if value.description.is_empty() || value.description.chars().count() > MAX_KEY_DESCRIPTION_LENGTH {
+            problems.push(format!("key {key:?} must have a description of 1-{MAX_KEY_DESCRIPTION_LENGTH} characters"));
+        }
+    }
+
+    if let Some(profile) = &game.application {
+        if profile.description.as_ref().is_some_and(|description| description.chars().count() > MAX_DESCRIPTION_LENGTH) {
+            problems.push(format!("application description must be at most {MAX_DESCRIPTION_LENGTH} characters"));
+        }
+
+        let tags = profile.tags.as_deref().unwrap_or_default();
+        if tags.len() > MAX_TAGS {
+            problems.push(format!("has {} application tags, Discord allows at most {MAX_TAGS}", tags.len()));
+        }
+
+        for tag in tags {
+            if tag.is_empty() || tag.chars().count() > MAX_TAG_LENGTH {
+                problems.push(format!("application tag {tag:?} must be 1-{MAX_TAG_LENGTH} characters"));
+            }
+        }
+    }
+
+    problems
+}
+
+/// Every file in `dir` along with the id of the game it holds, in id order.
+fn game_paths(dir: &Path) -> Result<Vec<(String, PathBuf)>, GameLoadError> {
+    let mut paths: Vec<_> = fs::read_dir(dir).map_err(|e| GameLoadError::Io(dir.to_path_buf(), e))?
+        .filter_map(Result::ok)
+        .filter(|game| game.file_type().is_ok_and(|t| t.is_file()))
+        .map(|game| (game.path().with_extension("").file_name().unwrap_or_default().to_string_lossy().into_owned(), game.path()))
+        .collect();
+    paths.sort();
+
+    Ok(paths)
+}
+
+fn read_game(path: &Path) -> Result<Game, Vec<GameLoadError>> {
+    let contents = fs::read_to_string(path).map_err(|e| vec![GameLoadError::Io(path.to_path_buf(), e)])?;
+    serde_yml::from_str(&contents).map_err(|e| vec![GameLoadError::Parse(path.to_path_buf(), e)])
+}
+
+fn check_game(path: &Path, game: &Game) -> Vec<GameLoadError> {
+    validate(game).into_iter()
+        .map(|problem| GameLoadError::Invalid(path.to_path_buf(), problem))
+        .collect()
+}
+
+/// Accepts each loaded game whose suffix, id and aliases don't clash with a
+/// game accepted before it. Games that are the same as in `previous` go
+/// first, so a clash is blamed on the game that was just changed or added,
+/// which falls back to its `previous` version or is left out.
+fn accept_unique<T: Clone>(mut loaded: Vec<(String, T)>, previous: impl Fn(&str) -> Option<T>, game: impl Fn(&T) -> &Game, errors: &mut Vec<GameLoadError>) -> BTreeMap<String, T> {
+    loaded.sort_by_key(|(id, value)| (previous(id).is_none_or(|old| game(&old) != game(value)), id.clone()));
+
+    let mut accepted = BTreeMap::new();
+    let mut suffixes = BTreeMap::new();
+    let mut taken = BTreeMap::new();
+
+    for (id, value) in loaded {
+        let chosen = match clash(&id, game(&value), &suffixes, &taken) {
+            None => Some(value),
+            Some(e) => {
+                errors.push(e);
+                previous(&id).filter(|old| game(old) != game(&value) && clash(&id, game(old), &suffixes, &taken).is_none())
+            }
+        };
+
+        if let Some(value) = chosen {
+            let chosen = game(&value);
+            suffixes.insert(chosen.suffix.clone(), id.clone());
+            taken.insert(id.clone(), id.clone());
+            taken.extend(chosen.aliases.iter().map(|alias| (alias.clone(), id.clone())));
+            accepted.insert(id, value);
+        }
+    }
+
+    accepted
+}
+
+/// Whether `game` would share a suffix, id or alias with a game that's
+/// already been accepted.
+fn clash(id: &str, game: &Game, suffixes: &BTreeMap<String, String>, taken: &BTreeMap<String, String>) -> Option<GameLoadError> {
+    if let Some(other) = suffixes.get(&game.suffix) {
+        return Some(GameLoadError::DuplicateSuffix(other.clone(), id.to_string(), game.suffix.clone()));
+    }
+
+    if let Some(other) = taken.get(id) {
+        return Some(GameLoadError::DuplicateAlias(other.clone(), id.to_string(), id.to_string()));
+    }
+
+    game.aliases.iter()
+        .find_map(|alias| Some(GameLoadError::DuplicateAlias(id.to_string(), alias.clone(), taken.get(alias)?.clone())))
+}
+
+/// The application credentials Soulfire needs to act for a game's Discord app.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct GameCredentials {
+    pub application_id: u64,
+    pub client_id: u64,
+    pub client_secret: String,
+    /// Verifies interactions Discord sends to the game's app. Without it,
+    /// the game's interactions endpoint is turned off.
+    pub public_key: Option<VerifyingKey>
+}
+
+impl GameCredentials {
+    /// Reads `APP_ID_<SUFFIX>`, `CLIENT_ID_<SUFFIX>`, `CLIENT_SECRET_<SUFFIX>` and
+    /// the optional `PUBLIC_KEY_<SUFFIX>` (or `[games.<SUFFIX>]` in the
+    /// settings file), reporting every one that is missing or malformed.
+    pub fn from_settings(settings: &Settings, suffix: &str) -> Result<Self, Vec<SettingError>> {
+        let public_key = settings.game(suffix).and_then(|s| s.public_key.as_deref())
+            .map(|key| hex::decode(key).ok()
+                .and_then(|key| key.try_into().ok())
+                .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+                .ok_or_else(|| SettingError::Invalid(format!("PUBLIC_KEY_{suffix}"), "must be a hex encoded Ed25519 public key")))
+            .transpose();
+
+        match (
+            settings.require_id(suffix, "APP_ID", |s| s.app_id.as_ref()),
+            settings.require_id(suffix, "CLIENT_ID", |s| s.client_id.as_ref()),
+            settings.require_secret(suffix, "CLIENT_SECRET", |s| s.client_secret.as_ref()),
+            public_key
+        ) {
+            (Ok(application_id), Ok(client_id), Ok(client_secret), Ok(public_key)) => Ok(Self { application_id, client_id, client_secret, public_key }),
+            (application_id, client_id, client_secret, public_key) => Err([application_id.err(), client_id.err(), client_secret.err(), public_key.err()]
+                .into_iter()
+                .flatten()
+                .collect())
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RegisteredGame {
+    pub game: Game,
+    pub credentials: GameCredentials
+}
+
+/// Every game Soulfire serves, keyed by game id and iterated in id order.
+#[derive(Clone, Default)]
+pub struct GameRegistry {
+    games: BTreeMap<String, RegisteredGame>
+}
+
+impl GameRegistry {
+    /// Loads every YAML file in `dir` as a game, using the file name as its
+    /// id and taking its credentials from `settings`. Fails with every
+    /// problem found if any game doesn't load.
+    pub fn from_dir(dir: impl AsRef<Path>, settings: &Settings) -> Result<Self, ConfigReport> {
+        let (registry, errors) = Self::load_dir(dir, settings, &Self::default());
+
+        if errors.is_empty() {
+            Ok(registry)
+        } else {
+            Err(ConfigReport(errors))
+        }
+    }
+
+    /// Loads every game in `dir`, returning what loaded along with what
+    /// didn't. A game that fails to load, or clashes with another game, keeps
+    /// its version from `previous` if it has one.
+    pub fn load_dir(dir: impl AsRef<Path>, settings: &Settings, previous: &GameRegistry) -> (Self, Vec<GameLoadError>) {
+        let paths = match game_paths(dir.as_ref()) {
+            Ok(paths) => paths,
+            Err(e) => return (previous.clone(), vec![e])
+        };
+
+        let mut loaded = Vec::new();
+        let mut errors = Vec::new();
+
+        for (id, path) in paths {
+            match Self::load_game(&path, settings) {
+                Ok((game, credentials)) => loaded.push((id, RegisteredGame { game, credentials })),
+                Err(e) => {
+                    if let Some(old) = previous.get(&id) {
+                        loaded.push((id, old.clone()));
+                    }
+
+                    errors.extend(e);
+                }
+            }
+        }
+
+        let games = accept_unique(loaded, |id| previous.get(id).cloned(), |game| &game.game, &mut errors);
+        (Self { games }, errors)
+    }
+
+    fn load_game(path: &Path, settings: &Settings) -> Result<(Game, GameCredentials), Vec<GameLoadError>> {
+        let yaml = read_game(path)?;
+        let mut errors = check_game(path, &yaml);
+
+        match GameCredentials::from_settings(settings, &yaml.suffix) {
+            Ok(credentials) if errors.is_empty() => Ok((yaml, credentials)),
+            Ok(_) => Err(errors),
+            Err(e) => {
+                errors.extend(e.into_iter().map(GameLoadError::from));
+                Err(errors)
+            }
+        }
+    }
+
+    /// Loads every game config in `dir` without the credentials needed to
+    /// serve them, for tools that only need the configs. Fails with every
+    /// problem found if any game doesn't load or clashes with another.
+    pub fn load_configs(dir: impl AsRef<Path>) -> Result<BTreeMap<String, Game>, ConfigReport> {
+        let paths = game_paths(dir.as_ref()).map_err(|e| ConfigReport(vec![e]))?;
+        let mut loaded = Vec::new();
+        let mut errors = Vec::new();
+
+        for (id, path) in paths {
+            match read_game(&path).map(|game| (check_game(&path, &game), game)) {
+                Ok((problems, game)) if problems.is_empty() => loaded.push((id, game)),
+                Ok((problems, _)) => errors.extend(problems),
+                Err(e) => errors.extend(e)
+            }
+        }
+
+        let games = accept_unique(loaded, |_| None, |game| game, &mut errors);
+        if errors.is_empty() {
+            Ok(games)
+        } else {
+            Err(ConfigReport(errors))
+        }
+    }
+
+    pub fn from_games(games: impl IntoIterator<Item = (String, Game, GameCredentials)>) -> Self {
+        let mut registry = Self::default();
+
+        for (id, game, credentials) in games {
+            registry.insert(&id, game, credentials);
+        }
+
+        registry
+    }
+
+    pub fn insert(&mut self, id: &str, game: Game, credentials: GameCredentials) {
+        self.games.insert(id.to_string(), RegisteredGame { game, credentials });
+    }
+
+    pub fn get(&self, id: &str) -> Option<&RegisteredGame> {
+        self.games.get(id)
+    }
+
+    /// The id of the game that has `alias` as one of its aliases.
+    pub fn canonical_id(&self, alias: &str) -> Option<&str> {
+        self.iter()
+            .find(|(_, game)| game.game.aliases.iter().any(|a| a == alias))
+            .map(|(id, _)| id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &RegisteredGame)> {
+        self.games.iter().map(|(id, game)| (id.as_str(), game))
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+}
+
+/// A registry that can be swapped out while the server is running. Readers
+/// get a snapshot that stays consistent for as long as they hold it.
+#[derive(Clone)]
+pub struct SharedRegistry {
+    current: Arc<RwLock<Arc<GameRegistry>>>,
+    source: Option<Arc<(PathBuf, Settings)>>
+}
+
+impl SharedRegistry {
+    pub fn new(registry: GameRegistry) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(registry))),
+            source: None
+        }
+    }
+
+    /// Loads the registry from `dir` with the credentials in `settings`,
+    /// remembering both so it can be reloaded. Reloads pick up changed game
+    /// configs; changed credentials need a restart.
+    pub fn from_dir(dir: impl Into<PathBuf>, settings: &Settings) -> Result<Self, ConfigReport> {
+        let dir = dir.into();
+
+        Ok(Self {
+            current: Arc::new(RwLock::new(Arc::new(GameRegistry::from_dir(&dir, settings)?))),
+            source: Some(Arc::new((dir, settings.clone())))
+        })
+    }
+
+    pub fn current(&self) -> Arc<GameRegistry> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Reloads every game from the directory this registry was loaded from
+    /// and swaps them in. Games that fail to load keep serving their last
+    /// good config; the failures are logged and returned.
+    pub fn reload(&self) -> Vec<GameLoadError> {
+        let Some(source) = &self.source else {
+            log::warn!("Not reloading games, they weren't loaded from a directory");
+            return Vec::new();
+        };
+
+        let (dir, settings) = source.as_ref();
+        let (registry, errors) = GameRegistry::load_dir(dir, settings, &self.current());
+        for e in &errors {
+            log::error!("Failed to reload a game, keeping its previous config: {e}");
+        }
+
+        log::info!("Reloaded {} games from {:?}", registry.len(), dir);
+        *self.current.write().unwrap() = Arc::new(registry);
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, env, fs};
+
+    use crate::{settings::{GameSecrets, SettingError, Settings}, ApplicationProfile, Game, GameStatus, Key, KeyType, UidConfig, UsernameConfig};
+
+    use super::{validate, GameCredentials, GameLoadError, GameRegistry, SharedRegistry};
+
+    /// Settings with working credentials for every game in `suffixes`.
+    fn credentials<'a>(suffixes: impl IntoIterator<Item = &'a str>) -> Settings {
+        Settings {
+            games: suffixes.into_iter().map(|suffix| (suffix.to_string(), GameSecrets {
+                app_id: Some("1".to_string()),
+                client_id: Some("2".to_string()),
+                client_secret: Some("secret".to_string()),
+                ..GameSecrets::default()
+            })).collect(),
+            ..Settings::default()
+        }
+    }
+
+    fn game(name: &str) -> Game {
+        Game {
+            name: name.to_string(),
+            main_page: None,
+            suffix: name.to_uppercase(),
+            uid: UidConfig { max_length: 10 },
+            username: UsernameConfig { optional: true, max_length: 16 },
+            keys: BTreeMap::default(),
+            status: GameStatus::Active,
+            status_message: None,
+            aliases: Vec::new(),
+            application: None
+        }
+    }
+
+    #[test]
+    fn test_registry_order() {
+        let registry = GameRegistry::from_games([
+            ("b".to_string(), game("b"), GameCredentials::default()),
+            ("a".to_string(), game("a"), GameCredentials::default()),
+            ("c".to_string(), game("c"), GameCredentials::default())
+        ]);
+
+        assert_eq!(vec!["a", "b", "c"], registry.iter().map(|(id, _)| id).collect::<Vec<_>>());
+        assert_eq!("b", registry.get("b").unwrap().game.name);
+        assert!(registry.get("d").is_none());
+    }
+
+    #[test]
+    fn test_aliases() {
+        let mut old = game("a");
+        old.aliases = vec!["old".to_string(), "b".to_string()];
+        let registry = GameRegistry::from_games([
+            ("a".to_string(), old, GameCredentials::default()),
+            ("b".to_string(), game("b"), GameCredentials::default())
+        ]);
+
+        assert_eq!(Some("a"), registry.canonical_id("old"));
+        assert_eq!(None, registry.canonical_id("a"));
+
+        let dir = env::temp_dir().join(format!("soulfire-aliases-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (id, game) in registry.iter() {
+            fs::write(dir.join(format!("{id}.yml")), serde_yml::to_string(&game.game).unwrap()).unwrap();
+        }
+
+        let (_, errors) = GameRegistry::load_dir(&dir, &credentials(["A", "B"]), &GameRegistry::default());
+        assert!(errors.iter().any(|e| matches!(e, GameLoadError::DuplicateAlias(id, alias, other) if id == "a" && alias == "b" && other == "b")));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload_keeps_good_config() {
+        let dir = env::temp_dir().join(format!("soulfire-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let yaml = |name: &str| format!("name: {name}\nsuffix: {}\nuid:\n  max_length: 10\nusername:\n  optional: true\n  max_length: 16\nkeys: {{}}\n", name.to_uppercase());
+        fs::write(dir.join("a.yml"), yaml("Before")).unwrap();
+        let registry = SharedRegistry::from_dir(&dir, &credentials(["BEFORE", "NEW"])).unwrap();
+        let before = registry.current();
+
+        fs::write(dir.join("a.yml"), "name: [this isn't a game").unwrap();
+        fs::write(dir.join("b.yml"), yaml("New")).unwrap();
+        assert_eq!(1, registry.reload().len());
+
+        let after = registry.current();
+        assert_eq!("Before", after.get("a").unwrap().game.name);
+        assert_eq!("New", after.get("b").unwrap().game.name);
+        assert!(before.get("b").is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload_rejects_duplicates() {
+        let dir = env::temp_dir().join(format!("soulfire-reload-duplicates-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let yaml = |name: &str, suffix: &str| format!("name: {name}\nsuffix: {suffix}\nuid:\n  max_length: 10\nusername:\n  optional: true\n  max_length: 16\nkeys: {{}}\n");
+        fs::write(dir.join("a.yml"), yaml("First", "FIRST")).unwrap();
+        fs::write(dir.join("b.yml"), yaml("Second", "SECOND")).unwrap();
+        let registry = SharedRegistry::from_dir(&dir, &credentials(["FIRST", "SECOND"])).unwrap();
+
+        // the earlier game changes to clash with the later one
+        fs::write(dir.join("a.yml"), yaml("Changed", "SECOND")).unwrap();
+        let errors = registry.reload();
+        assert_eq!(1, errors.len(), "{errors:?}");
+        assert!(matches!(&errors[0], GameLoadError::DuplicateSuffix(other, id, suffix) if other == "b" && id == "a" && suffix == "SECOND"));
+
+        let current = registry.current();
+        assert_eq!("First", current.get("a").unwrap().game.name);
+        assert_eq!("Second", current.get("b").unwrap().game.name);
+
+        // a new game that clashes is left out
+        fs::write(dir.join("c.yml"), yaml("Third", "FIRST")).unwrap();
+        assert_eq!(2, registry.reload().len());
+        assert!(registry.current().get("c").is_none());
+        assert_eq!("First", registry.current().get("a").unwrap().game.name);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_configs_without_credentials() {
+        let dir = env::temp_dir().join(format!("soulfire-configs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join("a.yml"), serde_yml::to_string(&game("nocredentials")).unwrap()).unwrap();
+        assert!(GameRegistry::from_dir(&dir, &Settings::default()).is_err());
+        assert_eq!("nocredentials", GameRegistry::load_configs(&dir).unwrap()["a"].name);
+
+        fs::write(dir.join("b.yml"), serde_yml::to_string(&game("nocredentials")).unwrap()).unwrap();
+        let report = GameRegistry::load_configs(&dir).err().unwrap();
+        assert!(matches!(&report.0[..], [GameLoadError::DuplicateSuffix(..)]));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_startup_report() {
+        let dir = env::temp_dir().join(format!("soulfire-report-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut settings = Settings::default();
+        settings.games.insert("REPORT".to_string(), GameSecrets { app_id: Some("not a number".to_string()), ..GameSecrets::default() });
+
+        let mut bad = game("report");
+        bad.keys.insert("Bad Key".to_string(), Key {
+            ty: KeyType::BoolEq { conditions: Vec::new() },
+            name: String::new(),
+            name_localizations: None,
+            description: "Fine".to_string(),
+            description_localizations: None
+        });
+        fs::write(dir.join("a.yml"), serde_yml::to_string(&bad).unwrap()).unwrap();
+        fs::write(dir.join("b.yml"), "name: [this isn't a game").unwrap();
+
+        let report = GameRegistry::from_dir(&dir, &settings).err().unwrap();
+        let errors = &report.0;
+        assert_eq!(6, errors.len(), "{report}");
+        assert!(matches!(&errors[0], GameLoadError::Invalid(_, problem) if problem.contains("\"Bad Key\"")));
+        assert!(matches!(&errors[2], GameLoadError::Setting(SettingError::Invalid(name, _)) if name == "APP_ID_REPORT"));
+        assert!(matches!(&errors[3], GameLoadError::Setting(SettingError::Missing(name)) if name == "CLIENT_ID_REPORT"));
+        assert!(matches!(&errors[5], GameLoadError::Parse(..)));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_application_profile() {
+        let mut profiled = game("profiled");
+        profiled.application = Some(ApplicationProfile { description: Some("A game".to_string()), tags: Some(vec!["rpg".to_string()]) });
+        assert!(validate(&profiled).is_empty());
+
+        profiled.application = Some(ApplicationProfile { description: None, tags: Some(["rpg", "", "a tag that's far too long", "d", "e", "f"].map(String::from).to_vec()) });
+        assert_eq!(3, validate(&profiled).len());
+    }
+
+    #[test]
+    fn test_duplicate_suffix() {
+        let dir = env::temp_dir().join(format!("soulfire-duplicate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join("a.yml"), serde_yml::to_string(&game("dup")).unwrap()).unwrap();
+        fs::write(dir.join("b.yml"), serde_yml::to_string(&game("dup")).unwrap()).unwrap();
+
+        let report = GameRegistry::from_dir(&dir, &credentials(["DUP"])).err().unwrap();
+        assert!(matches!(&report.0[..], [GameLoadError::DuplicateSuffix(a, b, suffix)] if a == "a" && b == "b" && suffix == "DUP"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,255 @@
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+
+use rocket::figment::{Error, Figment, providers::{Format, Serialized, Toml}, value::Value};
+use serde::{Deserialize, Deserializer};
+use thiserror::Error as ThisError;
+
+/// Environment variables that map straight onto a top-level setting.
+const ENV_SETTINGS: &[&str] = &[
+    "DOMAIN",
+    "GAMES_DIR",
+    "LOCALES_DIR",
+    "COMMANDS_FILE",
+    "ADMIN_TOKEN",
+    "DISCORD_BASE_URL",
+    "DISCORD_API_VERSION",
+    "TOKEN_KEY_ID",
+    "TOKEN_JWT_KEY",
+    "TOKEN_CIPHER_KEY",
+    "TOKEN_STORE",
+    "TOKEN_STORE_PATH"
+];
+
+/// Settings whose names end in `_file` because they are a path, rather than
+/// a setting to be read from a file.
+const PATH_SETTINGS: &[&str] = &["commands_file"];
+
+/// Environment variable prefixes that map onto a table of settings, keyed by
+/// whatever follows the prefix.
+const ENV_TABLES: &[(&str, &str, &str)] = &[
+    ("TOKEN_JWT_KEY_", "retired_keys", "jwt_key"),
+    ("TOKEN_CIPHER_KEY_", "retired_keys", "cipher_key"),
+    ("APP_ID_", "games", "app_id"),
+    ("CLIENT_ID_", "games", "client_id"),
+    ("CLIENT_SECRET_", "games", "client_secret"),
+    ("BOT_TOKEN_", "games", "bot_token"),
+    ("PUBLIC_KEY_", "games", "public_key")
+];
+
+/// A setting that's missing or can't be used, named by its environment variable.
+#[derive(ThisError, Debug)]
+pub enum SettingError {
+    #[error("{0} is not set")]
+    Missing(String),
+    #[error("{0} {1}")]
+    Invalid(String, &'static str)
+}
+
+const DEFAULT_DOMAIN: &str = "soulfire.derfrühling.net";
+
+/// Everything about a deployment that isn't a game config. Read from
+/// `Soulfire.toml` (or the file `SOULFIRE_CONFIG` names), with environment
+/// variables taking priority over the file.
+///
+/// Any setting can instead be read from a file by appending `_file` to its
+/// name (`_FILE` in the environment), which is how Docker hands out secrets.
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct Settings {
+    pub domain: Option<String>,
+    pub games_dir: Option<PathBuf>,
+    pub locales_dir: Option<PathBuf>,
+    pub commands_file: Option<PathBuf>,
+    pub admin_token: Option<String>,
+    pub discord_base_url: Option<String>,
+    #[serde(deserialize_with = "string_or_number")]
+    pub discord_api_version: Option<String>,
+    pub token_key_id: Option<String>,
+    pub token_jwt_key: Option<String>,
+    pub token_cipher_key: Option<String>,
+    pub retired_keys: BTreeMap<String, RetiredKeys>,
+    pub token_store: Option<String>,
+    pub token_store_path: Option<PathBuf>,
+    pub games: BTreeMap<String, GameSecrets>
+}
+
+/// The keys of a retired token key generation, from `[retired_keys.<ID>]`
+/// or `TOKEN_JWT_KEY_<ID>` and `TOKEN_CIPHER_KEY_<ID>`.
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct RetiredKeys {
+    pub jwt_key: Option<String>,
+    pub cipher_key: Option<String>
+}
+
+/// A game's Discord secrets, from `[games.<SUFFIX>]` or the
+/// `APP_ID_<SUFFIX>`-style environment variables.
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct GameSecrets {
+    #[serde(deserialize_with = "string_or_number")]
+    pub app_id: Option<String>,
+    #[serde(deserialize_with = "string_or_number")]
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub bot_token: Option<String>,
+    pub public_key: Option<String>
+}
+
+impl Settings {
+    pub fn load() -> Result<Self, Box<Error>> {
+        let path = env::var("SOULFIRE_CONFIG").unwrap_or_else(|_| "Soulfire.toml".to_string());
+        let mut figment = Figment::from(Toml::file(path));
+
+        for (name, value) in env::vars() {
+            if let Some(key) = env_key(&name) {
+                figment = figment.merge(Serialized::global(&key, value));
+            }
+        }
+
+        let mut value: Value = figment.extract()?;
+        read_files(&mut value)?;
+        Ok(value.deserialize()?)
+    }
+
+    /// Where Soulfire is served from.
+    pub fn domain(&self) -> &str {
+        self.domain.as_deref().unwrap_or(DEFAULT_DOMAIN)
+    }
+
+    pub fn game(&self, suffix: &str) -> Option<&GameSecrets> {
+        self.games.get(suffix)
+    }
+
+    /// The secret `field` picks from the game with `suffix`, or an error
+    /// naming it as `<NAME>_<SUFFIX>` if it isn't set.
+    pub fn require_secret(&self, suffix: &str, name: &str, field: impl Fn(&GameSecrets) -> Option<&String>) -> Result<String, SettingError> {
+        self.game(suffix).and_then(field).cloned()
+            .ok_or_else(|| SettingError::Missing(format!("{name}_{suffix}")))
+    }
+
+    /// Like [`Settings::require_secret`], for the ids Discord gives out.
+    pub fn require_id(&self, suffix: &str, name: &str, field: impl Fn(&GameSecrets) -> Option<&String>) -> Result<u64, SettingError> {
+        self.require_secret(suffix, name, field)?.parse()
+            .map_err(|_| SettingError::Invalid(format!("{name}_{suffix}"), "must be a number"))
+    }
+}
+
+/// Maps an environment variable onto the key path of the setting it
+/// overrides, if it overrides one.
+fn env_key(name: &str) -> Option<String> {
+    if ENV_SETTINGS.contains(&name) {
+        return Some(name.to_lowercase());
+    }
+
+    let (name, file) = match name.strip_suffix("_FILE") {
+        Some(name) => (name, "_file"),
+        None => (name, "")
+    };
+
+    if ENV_SETTINGS.contains(&name) {
+        return Some(format!("{}{file}", name.to_lowercase()));
+    }
+
+    ENV_TABLES.iter().find_map(|(prefix, table, key)| {
+        let id = name.strip_prefix(prefix).filter(|id| !id.is_empty() && !id.contains('.'))?;
+        Some(format!("{table}.{id}.{key}{file}"))
+    })
+}
+
+/// Replaces every `<name>_file` setting with `<name>`, read from that file.
+fn read_files(value: &mut Value) -> Result<(), Box<Error>> {
+    let Value::Dict(_, dict) = value else { return Ok(()) };
+    let files: Vec<_> = dict.keys().filter(|key| key.ends_with("_file") && !PATH_SETTINGS.contains(&key.as_str())).cloned().collect();
+
+    for key in files {
+        let name = key.strip_suffix("_file").unwrap_or_default().to_string();
+        let path = dict.remove(&key)
+            .and_then(Value::into_string)
+            .ok_or_else(|| Error::from(format!("{key} must be a path")))?;
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| Error::from(format!("failed to read {key} {path:?}: {e}")))?;
+
+        dict.insert(name, Value::from(contents.trim_end().to_string()));
+    }
+
+    dict.values_mut().try_for_each(read_files)
+}
+
+/// Ids come as strings from the environment but as numbers from TOML.
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(u64)
+    }
+
+    Ok(Option::<StringOrNumber>::deserialize(deserializer)?.map(|v| match v {
+        StringOrNumber::String(s) => s,
+        StringOrNumber::Number(n) => n.to_string()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::{env_key, GameSecrets, SettingError, Settings};
+
+    #[test]
+    fn test_env_keys() {
+        assert_eq!(Some("domain".to_string()), env_key("DOMAIN"));
+        assert_eq!(Some("token_jwt_key_file".to_string()), env_key("TOKEN_JWT_KEY_FILE"));
+        assert_eq!(Some("commands_file".to_string()), env_key("COMMANDS_FILE"));
+        assert_eq!(Some("commands_file_file".to_string()), env_key("COMMANDS_FILE_FILE"));
+        assert_eq!(Some("retired_keys.OLD.jwt_key".to_string()), env_key("TOKEN_JWT_KEY_OLD"));
+        assert_eq!(Some("games.HI3_GLB.client_secret_file".to_string()), env_key("CLIENT_SECRET_HI3_GLB_FILE"));
+        assert_eq!(None, env_key("APP_ID_"));
+        assert_eq!(None, env_key("PATH"));
+    }
+
+    #[test]
+    fn test_require_secret() {
+        let mut settings = Settings::default();
+        settings.games.insert("GAME".to_string(), GameSecrets { app_id: Some("123".to_string()), client_id: Some("abc".to_string()), ..GameSecrets::default() });
+
+        assert_eq!(123, settings.require_id("GAME", "APP_ID", |s| s.app_id.as_ref()).unwrap());
+        assert!(matches!(settings.require_id("GAME", "CLIENT_ID", |s| s.client_id.as_ref()), Err(SettingError::Invalid(name, _)) if name == "CLIENT_ID_GAME"));
+        assert!(matches!(settings.require_secret("GAME", "BOT_TOKEN", |s| s.bot_token.as_ref()), Err(SettingError::Missing(name)) if name == "BOT_TOKEN_GAME"));
+        assert!(matches!(settings.require_secret("OTHER", "BOT_TOKEN", |s| s.bot_token.as_ref()), Err(SettingError::Missing(name)) if name == "BOT_TOKEN_OTHER"));
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = env::temp_dir().join(format!("soulfire-settings-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("secret"), "from a file\n").unwrap();
+        fs::write(dir.join("Soulfire.toml"), format!(r#"
+            domain = "example.com"
+            commands_file = "config/commands.json"
+
+            [games.SETTINGS]
+            app_id = 123
+            client_id = "456"
+            client_secret_file = "{}"
+            bot_token = "overridden"
+        "#, dir.join("secret").display())).unwrap();
+
+        env::set_var("SOULFIRE_CONFIG", dir.join("Soulfire.toml"));
+        env::set_var("BOT_TOKEN_SETTINGS", "from the environment");
+        let settings = Settings::load();
+        env::remove_var("SOULFIRE_CONFIG");
+        let settings = settings.unwrap();
+
+        assert_eq!(Some("example.com"), settings.domain.as_deref());
+        assert_eq!(Some("config/commands.json".into()), settings.commands_file);
+        let game = settings.game("SETTINGS").unwrap();
+        assert_eq!(Some("123"), game.app_id.as_deref());
+        assert_eq!(Some("456"), game.client_id.as_deref());
+        assert_eq!(Some("from a file"), game.client_secret.as_deref());
+        assert_eq!(Some("from the environment"), game.bot_token.as_deref());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
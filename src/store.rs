@@ -0,0 +1,253 @@
+use std::{collections::HashMap, fs, io, path::PathBuf, sync::{Arc, Mutex}};
+
+use aes_gcm::aead::{OsRng, rand_core::RngCore};
+use serde::{Serialize, Deserialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::{settings::{SettingError, Settings}, InvalidToken, Keyring};
+
+/// Somewhere to keep Discord tokens between the OAuth callback and the form
+/// submission. Whatever `issue` returns is what ends up in the user's cookie.
+pub trait TokenStore: Send + Sync {
+    fn issue(&self, token: &str, game: &str, ttl: Duration) -> String;
+    fn redeem(&self, cookie: &str, game: &str) -> Result<String, InvalidToken>;
+    fn revoke(&self, cookie: &str);
+}
+
+/// Which [`TokenStore`] to use, picked before there's a keyring to open it with.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TokenStoreKind {
+    Cookie,
+    Memory,
+    File(PathBuf)
+}
+
+impl TokenStoreKind {
+    /// Reads `token_store` (`cookie`, `memory` or `file`), defaulting to
+    /// encrypted cookies. The file store is kept at `token_store_path`, or
+    /// `sessions.json` if that isn't set.
+    pub fn from_settings(settings: &Settings) -> Result<Self, SettingError> {
+        match settings.token_store.as_deref() {
+            None | Some("cookie") => Ok(Self::Cookie),
+            Some("memory") => Ok(Self::Memory),
+            Some("file") => Ok(Self::File(settings.token_store_path.clone().unwrap_or_else(|| "sessions.json".into()))),
+            Some(_) => Err(SettingError::Invalid("TOKEN_STORE".to_string(), "must be cookie, memory or file"))
+        }
+    }
+
+    pub fn open(self, keyring: Arc<Keyring>) -> Box<dyn TokenStore> {
+        match self {
+            Self::Cookie => Box::new(EncryptedCookieStore(keyring)),
+            Self::Memory => Box::new(MemoryTokenStore::default()),
+            Self::File(path) => Box::new(FileTokenStore::open(path, keyring))
+        }
+    }
+}
+
+/// Keeps the whole token in the cookie, encrypted. The server holds nothing.
+pub struct EncryptedCookieStore(pub Arc<Keyring>);
+
+impl TokenStore for EncryptedCookieStore {
+    fn issue(&self, token: &str, game: &str, ttl: Duration) -> String {
+        self.0.encrypt(token, game, ttl)
+    }
+
+    fn redeem(&self, cookie: &str, game: &str) -> Result<String, InvalidToken> {
+        self.0.decrypt(cookie, game)
+    }
+
+    fn revoke(&self, _cookie: &str) {}
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Session {
+    game: String,
+    token: String,
+    expires: i64
+}
+
+fn new_session_id() -> String {
+    let mut id = [0u8; 32];
+    OsRng.fill_bytes(&mut id);
+    hex::encode(id)
+}
+
+/// Keeps tokens in memory and only hands out an opaque session id. Sessions
+/// are lost on restart.
+#[derive(Default)]
+pub struct MemoryTokenStore {
+    sessions: Mutex<HashMap<String, Session>>
+}
+
+impl MemoryTokenStore {
+    fn insert(&self, session: Session) -> String {
+        let id = new_session_id();
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        sessions.retain(|_, s| s.expires > now);
+        sessions.insert(id.clone(), session);
+        id
+    }
+
+    fn get(&self, id: &str, game: &str) -> Result<String, InvalidToken> {
+        let sessions = self.sessions.lock().unwrap();
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        match sessions.get(id) {
+            Some(session) if session.game == game && session.expires > now => Ok(session.token.clone()),
+            _ => Err(InvalidToken)
+        }
+    }
+
+    fn remove(&self, id: &str) -> bool {
+        self.sessions.lock().unwrap().remove(id).is_some()
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn issue(&self, token: &str, game: &str, ttl: Duration) -> String {
+        self.insert(Session {
+            game: game.to_string(),
+            token: token.to_string(),
+            expires: (OffsetDateTime::now_utc() + ttl).unix_timestamp()
+        })
+    }
+
+    fn redeem(&self, cookie: &str, game: &str) -> Result<String, InvalidToken> {
+        self.get(cookie, game)
+    }
+
+    fn revoke(&self, cookie: &str) {
+        self.remove(cookie);
+    }
+}
+
+/// Like [`MemoryTokenStore`], but written through to a JSON file so sessions
+/// survive restarts. Tokens are encrypted at rest with the token keyring.
+pub struct FileTokenStore {
+    path: PathBuf,
+    memory: MemoryTokenStore,
+    keyring: Arc<Keyring>
+}
+
+impl FileTokenStore {
+    /// Opens the store at `path`. A missing or unreadable file starts it
+    /// empty, which only costs users whose sign-in was in progress.
+    pub fn open(path: impl Into<PathBuf>, keyring: Arc<Keyring>) -> Self {
+        let path = path.into();
+        let sessions = match fs::read_to_string(&path).map(|contents| serde_json::from_str(&contents)) {
+            Ok(Ok(sessions)) => sessions,
+            Ok(Err(e)) => {
+                log::error!("failed to parse token store {path:?}, starting it empty: {e}");
+                HashMap::default()
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::default(),
+            Err(e) => {
+                log::error!("failed to read token store {path:?}, starting it empty: {e}");
+                HashMap::default()
+            }
+        };
+
+        Self {
+            path,
+            memory: MemoryTokenStore { sessions: Mutex::new(sessions) },
+            keyring
+        }
+    }
+
+    /// Writes every session to a temporary file next to the store, then
+    /// moves it over the store, so a crash never leaves half a file behind.
+    fn save(&self) {
+        let sessions = self.memory.sessions.lock().unwrap();
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+
+        let result = serde_json::to_string(&*sessions).map_err(io::Error::from)
+            .and_then(|json| fs::write(&temp, json))
+            .and_then(|()| fs::rename(&temp, &self.path));
+
+        if let Err(e) = result {
+            log::error!("failed to save token store to {:?}: {e}", self.path);
+        }
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn issue(&self, token: &str, game: &str, ttl: Duration) -> String {
+        let id = self.memory.insert(Session {
+            game: game.to_string(),
+            token: self.keyring.encrypt(token, game, ttl),
+            expires: (OffsetDateTime::now_utc() + ttl).unix_timestamp()
+        });
+
+        self.save();
+        id
+    }
+
+    fn redeem(&self, cookie: &str, game: &str) -> Result<String, InvalidToken> {
+        self.keyring.decrypt(&self.memory.get(cookie, game)?, game)
+    }
+
+    fn revoke(&self, cookie: &str) {
+        if self.memory.remove(cookie) {
+            self.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, sync::Arc};
+
+    use time::Duration;
+
+    use crate::{Keyring, TokenKeys};
+
+    use super::{FileTokenStore, MemoryTokenStore, TokenStore};
+
+    fn keyring() -> Arc<Keyring> {
+        Arc::new(Keyring::new("0", TokenKeys::new(&[1; 32], [2; 32])))
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let store = MemoryTokenStore::default();
+        let id = store.issue("token", "test", Duration::minutes(5));
+
+        assert_eq!("token", store.redeem(&id, "test").unwrap());
+        assert!(store.redeem(&id, "other-game").is_err());
+
+        store.revoke(&id);
+        assert!(store.redeem(&id, "test").is_err());
+
+        let expired = store.issue("token", "test", Duration::seconds(-1));
+        assert!(store.redeem(&expired, "test").is_err());
+    }
+
+    #[test]
+    fn test_file_store() {
+        let path = env::temp_dir().join(format!("soulfire-sessions-{}.json", std::process::id()));
+        let id = FileTokenStore::open(&path, keyring()).issue("token", "test", Duration::minutes(5));
+
+        let reopened = FileTokenStore::open(&path, keyring());
+        assert_eq!("token", reopened.redeem(&id, "test").unwrap());
+        assert!(!fs::read_to_string(&path).unwrap().contains("\"token\":\"token\""));
+
+        reopened.revoke(&id);
+        assert!(FileTokenStore::open(&path, keyring()).redeem(&id, "test").is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_recovers() {
+        let path = env::temp_dir().join(format!("soulfire-sessions-truncated-{}.json", std::process::id()));
+        fs::write(&path, "{\"abc\": {\"game\": \"te").unwrap();
+
+        let store = FileTokenStore::open(&path, keyring());
+        let id = store.issue("token", "test", Duration::minutes(5));
+        assert_eq!("token", FileTokenStore::open(&path, keyring()).redeem(&id, "test").unwrap());
+        assert!(!path.with_extension("json.tmp").exists());
+        fs::remove_file(path).unwrap();
+    }
+}
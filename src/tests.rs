@@ -0,0 +1,458 @@
+use std::{env, fs, path::PathBuf, sync::{Arc, Once}};
+
+use ed25519_dalek::{Signer, SigningKey};
+use rocket::{http::{ContentType, Header, Status}, local::asynchronous::{Client, LocalResponse}};
+use serde_json::json;
+use soulfire::{discord::{DiscordConfig, MockDiscordApi}, locales::Locales, Keyring, TokenKeys, registry::SharedRegistry, settings::Settings, store::EncryptedCookieStore, webhooks::EventCounters};
+
+use crate::{rocket, BotInfo};
+
+const GAME: &str = r#"
+name: "Test Game"
+suffix: TEST
+aliases:
+    - old-test
+uid:
+    max_length: 10
+username:
+    optional: true
+    max_length: 16
+keys:
+    is_na:
+        type: BoolEq
+        name: "NA"
+        description: "Your profile must be on the NA server."
+        conditions:
+            - uid:
+                  start: 100000000
+                  end: 200000000
+    is_eu:
+        type: BoolEq
+        name: "EU"
+        description: "Your profile must be on the EU server."
+        conditions:
+            - uid:
+                  start: 200000000
+                  end: 300000000
+"#;
+
+const CLOSED_GAME: &str = r#"
+name: "Closed Game"
+suffix: SUFFIX
+status: STATUS
+uid:
+    max_length: 10
+username:
+    optional: true
+    max_length: 16
+keys:
+    is_na:
+        type: BoolEq
+        name: "NA"
+        description: "Your profile must be on the NA server."
+        conditions:
+            - uid:
+                  start: 100000000
+                  end: 200000000
+"#;
+
+static SETUP: Once = Once::new();
+
+/// Writes a temporary games directory holding an active `test` game plus a
+/// `maintenance` and a `retired` one, and sets up credentials and token keys
+/// to match.
+fn setup() -> PathBuf {
+    let dir = env::temp_dir().join(format!("soulfire-games-{}", std::process::id()));
+
+    SETUP.call_once(|| {
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("test.yml"), GAME).unwrap();
+
+        for (id, suffix) in [("maintenance", "MAINTENANCE"), ("retired", "RETIRED")] {
+            fs::write(dir.join(format!("{id}.yml")), CLOSED_GAME.replace("SUFFIX", suffix).replace("STATUS", id)).unwrap();
+        }
+
+        env::set_var("PUBLIC_KEY_TEST", hex::encode(interaction_key().verifying_key().to_bytes()));
+
+        for suffix in ["TEST", "MAINTENANCE", "RETIRED"] {
+            env::set_var(format!("APP_ID_{suffix}"), "1");
+            env::set_var(format!("CLIENT_ID_{suffix}"), "2");
+            env::set_var(format!("CLIENT_SECRET_{suffix}"), "secret");
+        }
+    });
+
+    dir
+}
+
+fn interaction_key() -> SigningKey {
+    SigningKey::from_bytes(&[7; 32])
+}
+
+async fn client(discord: &Arc<MockDiscordApi>) -> Client {
+    let dir = setup();
+
+    let keyring = Arc::new(Keyring::new("0", TokenKeys::new(&[1; 32], [2; 32])));
+
+    Client::tracked(rocket(BotInfo {
+        domain: "soulfire.test".to_string(),
+        tokens: Box::new(EncryptedCookieStore(keyring.clone())),
+        keyring,
+        discord: discord.clone(),
+        discord_config: DiscordConfig::default(),
+        admin_token: Some("admin".to_string()),
+        locales: Locales::from_dir("locales").unwrap(),
+        events: EventCounters::default()
+    }, SharedRegistry::from_dir(dir, &Settings::load().unwrap()).unwrap())).await.unwrap()
+}
+
+fn location<'a>(res: &'a LocalResponse<'_>) -> &'a str {
+    res.headers().get_one("Location").unwrap_or_default()
+}
+
+async fn log_in(client: &Client, code: &str) {
+    log_in_to(client, "test", code).await;
+}
+
+async fn log_in_to(client: &Client, game: &str, code: &str) {
+    let res = client.get(format!("/games/{game}/discord-auth-flow?code={code}")).dispatch().await;
+    assert_eq!(Status::SeeOther, res.status());
+    assert_eq!(format!("/games/{game}/link?hpn"), location(&res));
+}
+
+async fn submit<'c>(client: &'c Client, form: &str) -> LocalResponse<'c> {
+    submit_to(client, "test", form).await
+}
+
+async fn submit_to<'c>(client: &'c Client, game: &str, form: &str) -> LocalResponse<'c> {
+    client.post(format!("/games/{game}/link"))
+        .header(ContentType::Form)
+        .body(form)
+        .dispatch().await
+}
+
+#[rocket::async_test]
+async fn test_link_flow() {
+    let discord = Arc::new(MockDiscordApi::default().with_code("abc", "token"));
+    let client = client(&discord).await;
+
+    log_in(&client, "abc").await;
+    assert!(client.cookies().get("dstk").is_some());
+
+    let res = submit(&client, "uid=150000000&username=Tester").await;
+    assert_eq!(Status::SeeOther, res.status());
+    assert_eq!("/success", location(&res));
+    assert!(client.cookies().get("dstk").is_none());
+
+    assert_eq!(Some(&json!({
+        "platform_name": "Test Game",
+        "platform_username": "Tester (150000000)",
+        "metadata": { "is_na": "1", "is_eu": "0" }
+    })), discord.state().role_connections.get(&(1, "token".to_string())));
+
+    let page = client.get("/games/test/link").dispatch().await.into_string().await.unwrap();
+    assert!(page.contains("client_id&#x3D;2&amp;"));
+    assert!(page.contains("value=\"150000000\""));
+}
+
+#[rocket::async_test]
+async fn test_auth_flow_errors() {
+    let discord = Arc::new(MockDiscordApi::default());
+    let client = client(&discord).await;
+
+    let res = client.get("/games/test/discord-auth-flow?code=not%20valid").dispatch().await;
+    assert_eq!(Status::BadRequest, res.status());
+
+    let res = client.get("/games/test/discord-auth-flow?code=unknown").dispatch().await;
+    assert_eq!(Status::BadRequest, res.status());
+    assert!(client.cookies().get("dstk").is_none());
+
+    let res = client.get("/games/missing/discord-auth-flow?code=abc").dispatch().await;
+    assert_eq!(Status::NotFound, res.status());
+}
+
+#[rocket::async_test]
+async fn test_missing_token_reauthorizes() {
+    let discord = Arc::new(MockDiscordApi::default());
+    let client = client(&discord).await;
+
+    let res = submit(&client, "uid=250000000&username=").await;
+    assert_eq!(Status::SeeOther, res.status());
+    assert!(location(&res).starts_with("https://discord.com/oauth2/authorize?client_id=2&"));
+    assert!(discord.state().role_connections.is_empty());
+}
+
+#[rocket::async_test]
+async fn test_revoked_token_keeps_input() {
+    let discord = Arc::new(MockDiscordApi::default()
+        .with_code("first", "revoked")
+        .with_code("second", "fresh"));
+    let client = client(&discord).await;
+
+    log_in(&client, "first").await;
+    discord.state().tokens.remove("revoked");
+
+    let res = submit(&client, "uid=250000000&username=Tester").await;
+    assert_eq!(Status::SeeOther, res.status());
+    assert!(location(&res).contains("/oauth2/authorize"));
+    assert!(client.cookies().get("dstk").is_none());
+
+    log_in(&client, "second").await;
+    let res = client.post("/games/test/reapply").dispatch().await;
+    assert_eq!("/success", location(&res));
+    assert_eq!(
+        Some(&json!({ "is_na": "0", "is_eu": "1" })),
+        discord.state().role_connections.get(&(1, "fresh".to_string())).map(|v| &v["metadata"])
+    );
+}
+
+#[rocket::async_test]
+async fn test_discord_errors() {
+    let discord = Arc::new(MockDiscordApi::default()
+        .with_code("a", "token-a")
+        .with_code("b", "token-b"));
+    let client = client(&discord).await;
+
+    log_in(&client, "a").await;
+    discord.state().failures.push_back(500);
+    assert_eq!(Status::InternalServerError, submit(&client, "uid=1&username=").await.status());
+
+    log_in(&client, "b").await;
+    discord.state().failures.push_back(429);
+    assert_eq!(Status::ServiceUnavailable, submit(&client, "uid=1&username=").await.status());
+
+    assert!(discord.state().role_connections.is_empty());
+}
+
+#[rocket::async_test]
+async fn test_unknown_game() {
+    let discord = Arc::new(MockDiscordApi::default());
+    let client = client(&discord).await;
+
+    assert_eq!(Status::NotFound, client.get("/games/missing").dispatch().await.status());
+    assert_eq!(Status::NotFound, client.get("/games/missing/link").dispatch().await.status());
+    assert_eq!(Status::NotFound, client.post("/games/missing/link")
+        .header(ContentType::Form)
+        .body("uid=1&username=")
+        .dispatch().await.status());
+}
+
+#[rocket::async_test]
+async fn test_admin_reload() {
+    let discord = Arc::new(MockDiscordApi::default());
+    let client = client(&discord).await;
+
+    assert_eq!(Status::Unauthorized, client.post("/admin/reload").dispatch().await.status());
+    assert_eq!(Status::Unauthorized, client.post("/admin/reload")
+        .header(Header::new("Authorization", "Bearer wrong"))
+        .dispatch().await.status());
+
+    let res = client.post("/admin/reload")
+        .header(Header::new("Authorization", "Bearer admin"))
+        .dispatch().await;
+    assert_eq!(Status::Ok, res.status());
+    assert_eq!(json!({ "games": 3, "errors": [] }), res.into_json::<serde_json::Value>().await.unwrap());
+}
+
+#[rocket::async_test]
+async fn test_game_status() {
+    let discord = Arc::new(MockDiscordApi::default()
+        .with_code("a", "token-a")
+        .with_code("b", "token-b"));
+    let client = client(&discord).await;
+
+    let page = client.get("/games/maintenance/link").dispatch().await.into_string().await.unwrap();
+    assert!(page.contains("under maintenance"));
+    assert!(!page.contains("action=\"/games/maintenance/link\""));
+    assert!(!page.contains("/unlink"));
+
+    log_in_to(&client, "maintenance", "a").await;
+    assert_eq!(Status::ServiceUnavailable, submit_to(&client, "maintenance", "uid=150000000&username=").await.status());
+    assert_eq!(Status::ServiceUnavailable, client.post("/games/maintenance/unlink").dispatch().await.status());
+
+    let page = client.get("/games/retired/link").dispatch().await.into_string().await.unwrap();
+    assert!(page.contains("has been retired"));
+    assert!(!page.contains("action=\"/games/retired/link\""));
+    assert!(page.contains("action=\"/games/retired/unlink\""));
+
+    log_in_to(&client, "retired", "b").await;
+    assert_eq!(Status::Gone, submit_to(&client, "retired", "uid=150000000&username=").await.status());
+
+    let res = client.post("/games/retired/unlink").dispatch().await;
+    assert_eq!("/success?unlinked", location(&res));
+    assert_eq!(Some(&json!({
+        "platform_name": "Closed Game",
+        "platform_username": "",
+        "metadata": {}
+    })), discord.state().role_connections.get(&(1, "token-b".to_string())));
+
+    let page = client.get("/all-games").dispatch().await.into_string().await.unwrap();
+    assert!(page.contains("<span class=\"status-badge\">Maintenance</span>"));
+    assert!(page.contains("<span class=\"status-badge\">Retired</span>"));
+}
+
+#[rocket::async_test]
+async fn test_aliases() {
+    let discord = Arc::new(MockDiscordApi::default());
+    let client = client(&discord).await;
+
+    let game = client.get("/games/old-test").dispatch().await.into_json::<serde_json::Value>().await.unwrap();
+    assert_eq!("Test Game", game["name"]);
+
+    let res = client.get("/games/old-test/link?hpn").dispatch().await;
+    assert_eq!(Status::PermanentRedirect, res.status());
+    assert_eq!("/games/test/link?hpn", location(&res));
+
+    let res = submit_to(&client, "old-test", "uid=1&username=").await;
+    assert_eq!(Status::PermanentRedirect, res.status());
+    assert_eq!("/games/test/link", location(&res));
+}
+
+async fn interact<'c>(client: &'c Client, game: &str, body: serde_json::Value) -> LocalResponse<'c> {
+    signed_post(client, format!("/games/{game}/interactions"), body).await
+}
+
+async fn signed_post<'c>(client: &'c Client, uri: String, body: serde_json::Value) -> LocalResponse<'c> {
+    let body = body.to_string();
+    let signature = interaction_key().sign(format!("1700000000{body}").as_bytes());
+
+    client.post(uri)
+        .header(Header::new("X-Signature-Ed25519", hex::encode(signature.to_bytes())))
+        .header(Header::new("X-Signature-Timestamp", "1700000000"))
+        .body(body)
+        .dispatch().await
+}
+
+#[rocket::async_test]
+async fn test_interactions() {
+    let discord = Arc::new(MockDiscordApi::default());
+    let client = client(&discord).await;
+
+    assert_eq!(Status::Unauthorized, client.post("/games/test/interactions").body(r#"{"type":1}"#).dispatch().await.status());
+    assert_eq!(Status::Unauthorized, client.post("/games/test/interactions")
+        .header(Header::new("X-Signature-Ed25519", "00".repeat(64)))
+        .header(Header::new("X-Signature-Timestamp", "1700000000"))
+        .body(r#"{"type":1}"#)
+        .dispatch().await.status());
+    assert_eq!(Status::NotFound, interact(&client, "retired", json!({ "type": 1 })).await.status());
+
+    let res = interact(&client, "test", json!({ "type": 1 })).await;
+    assert_eq!(json!({ "type": 1 }), res.into_json::<serde_json::Value>().await.unwrap());
+
+    let res = interact(&client, "old-test", json!({ "type": 1 })).await;
+    assert_eq!(json!({ "type": 1 }), res.into_json::<serde_json::Value>().await.unwrap());
+
+    // well past Rocket's default limit for strings, like an interaction with many resolved members
+    assert_eq!(Status::Ok, interact(&client, "test", json!({ "type": 1, "padding": "x".repeat(64 * 1024) })).await.status());
+    assert_eq!(Status::PayloadTooLarge, interact(&client, "test", json!({ "type": 1, "padding": "x".repeat(1024 * 1024) })).await.status());
+
+    let help = interact(&client, "test", json!({ "type": 2, "data": { "name": "help" } })).await
+        .into_json::<serde_json::Value>().await.unwrap();
+    assert_eq!(json!(4), help["type"]);
+    assert_eq!(json!(64), help["data"]["flags"]);
+    assert_eq!(json!([{
+        "type": 1,
+        "components": [{ "type": 2, "style": 5, "label": "Link Test Game", "url": "https://soulfire.test/games/test/link" }]
+    }]), help["data"]["components"]);
+
+    let post_help = interact(&client, "test", json!({ "type": 2, "data": { "name": "post-help" } })).await
+        .into_json::<serde_json::Value>().await.unwrap();
+    assert!(post_help["data"].get("flags").is_none());
+    assert_eq!(help["data"]["components"], post_help["data"]["components"]);
+}
+
+#[rocket::async_test]
+async fn test_link_command() {
+    let discord = Arc::new(MockDiscordApi::default());
+    let client = client(&discord).await;
+
+    let link = |options: serde_json::Value| json!({ "type": 2, "data": { "name": "link", "options": options } });
+    let button = json!([{
+        "type": 1,
+        "components": [{ "type": 2, "style": 5, "label": "Link Test Game", "url": "https://soulfire.test/games/test/link" }]
+    }]);
+
+    let res = interact(&client, "test", link(json!([]))).await.into_json::<serde_json::Value>().await.unwrap();
+    assert_eq!(json!(64), res["data"]["flags"]);
+    assert_eq!(button, res["data"]["components"]);
+
+    let res = interact(&client, "test", link(json!([{ "name": "game", "type": 3, "value": "old-test" }]))).await
+        .into_json::<serde_json::Value>().await.unwrap();
+    assert_eq!(button, res["data"]["components"]);
+
+    let res = interact(&client, "test", link(json!([{ "name": "game", "type": 3, "value": "retired" }]))).await
+        .into_json::<serde_json::Value>().await.unwrap();
+    assert!(res["data"].get("components").is_none());
+
+    let res = interact(&client, "test", json!({
+        "type": 4,
+        "data": { "name": "link", "options": [{ "name": "game", "type": 3, "value": "tes", "focused": true }] }
+    })).await.into_json::<serde_json::Value>().await.unwrap();
+    assert_eq!(json!({ "type": 8, "data": { "choices": [{ "name": "Test Game", "value": "test" }] } }), res);
+}
+
+#[rocket::async_test]
+async fn test_status_command() {
+    let discord = Arc::new(MockDiscordApi::default());
+    let client = client(&discord).await;
+
+    let status = |options: serde_json::Value| json!({ "type": 2, "data": { "name": "status", "options": options } });
+
+    let res = interact(&client, "test", status(json!([]))).await.into_json::<serde_json::Value>().await.unwrap();
+    let content = res["data"]["content"].as_str().unwrap();
+    assert!(content.contains("can't see what you currently have linked"));
+    assert!(content.contains("Add your UID"));
+
+    let res = interact(&client, "test", status(json!([{ "name": "uid", "type": 4, "value": 250000000 }]))).await
+        .into_json::<serde_json::Value>().await.unwrap();
+    let content = res["data"]["content"].as_str().unwrap();
+    assert!(content.contains("❌ **NA**"));
+    assert!(content.contains("✅ **EU**"));
+
+    let res = interact(&client, "test", status(json!([{ "name": "uid", "type": 4, "value": 12345678901u64 }]))).await
+        .into_json::<serde_json::Value>().await.unwrap();
+    assert!(res["data"]["content"].as_str().unwrap().contains("too long"));
+}
+
+#[rocket::async_test]
+async fn test_localized_interactions() {
+    let discord = Arc::new(MockDiscordApi::default());
+    let client = client(&discord).await;
+
+    let res = interact(&client, "test", json!({ "type": 2, "locale": "de", "data": { "name": "help" } })).await
+        .into_json::<serde_json::Value>().await.unwrap();
+    assert!(res["data"]["content"].as_str().unwrap().starts_with("Soulfire vergibt dir"));
+    assert_eq!(json!("Test Game verknüpfen"), res["data"]["components"][0]["components"][0]["label"]);
+
+    let res = interact(&client, "test", json!({ "type": 2, "locale": "de", "guild_locale": "en-US", "data": { "name": "post-help" } })).await
+        .into_json::<serde_json::Value>().await.unwrap();
+    assert!(res["data"]["content"].as_str().unwrap().starts_with("Soulfire gives you"));
+}
+
+#[rocket::async_test]
+async fn test_webhook_events() {
+    let discord = Arc::new(MockDiscordApi::default());
+    let client = client(&discord).await;
+    let event = |ty: &str, data: serde_json::Value| json!({ "version": 1, "application_id": "1", "type": 1, "event": { "type": ty, "timestamp": "2024-10-18T14:42:53.064834", "data": data } });
+
+    assert_eq!(Status::NoContent, signed_post(&client, "/games/test/webhook-events".into(), json!({ "version": 1, "application_id": "1", "type": 0 })).await.status());
+    assert_eq!(Status::Unauthorized, client.post("/games/test/webhook-events").header(Header::new("X-Signature-Ed25519", "00")).header(Header::new("X-Signature-Timestamp", "1")).body("{}").dispatch().await.status());
+    assert_eq!(Status::NotFound, signed_post(&client, "/games/maintenance/webhook-events".into(), json!({ "type": 0 })).await.status());
+    assert_eq!(Status::NoContent, signed_post(&client, "/games/test/webhook-events".into(), json!({ "type": 0, "padding": "x".repeat(64 * 1024) })).await.status());
+
+    for (ty, data) in [
+        ("APPLICATION_AUTHORIZED", json!({ "integration_type": 0, "scopes": ["applications.commands"], "user": { "id": "2" }, "guild": { "id": "3" } })),
+        ("APPLICATION_AUTHORIZED", json!({ "integration_type": 1, "scopes": ["applications.commands"], "user": { "id": "2" } })),
+        ("APPLICATION_DEAUTHORIZED", json!({ "user": { "id": "2" } })),
+        ("ENTITLEMENT_CREATE", json!({}))
+    ] {
+        assert_eq!(Status::NoContent, signed_post(&client, "/games/test/webhook-events".into(), event(ty, data)).await.status());
+    }
+
+    // events sent to an alias are counted under the game's current id
+    let deauthorized = event("APPLICATION_DEAUTHORIZED", json!({ "user": { "id": "4" } }));
+    assert_eq!(Status::NoContent, signed_post(&client, "/games/old-test/webhook-events".into(), deauthorized).await.status());
+
+    assert_eq!(Status::Unauthorized, client.get("/admin/events").dispatch().await.status());
+    let counts: serde_json::Value = client.get("/admin/events").header(Header::new("Authorization", "Bearer admin")).dispatch().await.into_json().await.unwrap();
+    assert_eq!(json!({ "test": { "guild_authorizations": 1, "user_authorizations": 1, "deauthorizations": 2 } }), counts);
+}
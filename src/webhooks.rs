@@ -0,0 +1,96 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use serde::{Serialize, Deserialize};
+use serde_repr::{Serialize_repr, Deserialize_repr};
+
+/// What Discord sends to an application's webhook events URL.
+#[derive(Deserialize, Debug)]
+pub struct WebhookEvent {
+    #[serde(rename = "type")]
+    pub ty: WebhookType,
+    #[serde(default)]
+    pub event: Option<EventBody>
+}
+
+#[derive(Serialize_repr, Deserialize_repr, Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum WebhookType {
+    Ping = 0,
+    Event = 1
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EventBody {
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default)]
+    pub data: EventData
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct EventData {
+    /// 0 when added to a server, 1 when added to a user's account.
+    #[serde(default)]
+    pub integration_type: Option<u8>,
+    #[serde(default)]
+    pub user: Option<Snowflake>,
+    #[serde(default)]
+    pub guild: Option<Snowflake>
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Snowflake {
+    pub id: String
+}
+
+/// How often each game's app has been added or removed since startup.
+#[derive(Serialize, Default, Clone, PartialEq, Eq, Debug)]
+pub struct GameEventCounts {
+    pub guild_authorizations: u64,
+    pub user_authorizations: u64,
+    pub deauthorizations: u64
+}
+
+#[derive(Default)]
+pub struct EventCounters {
+    counts: Mutex<BTreeMap<String, GameEventCounts>>
+}
+
+impl EventCounters {
+    /// Counts `event` for `game`, returning whether it was one Soulfire tracks.
+    pub fn record(&self, game: &str, event: &EventBody) -> bool {
+        let count: fn(&mut GameEventCounts) -> &mut u64 = match (event.ty.as_str(), event.data.integration_type) {
+            ("APPLICATION_AUTHORIZED", Some(1)) => |counts| &mut counts.user_authorizations,
+            ("APPLICATION_AUTHORIZED", _) => |counts| &mut counts.guild_authorizations,
+            ("APPLICATION_DEAUTHORIZED", _) => |counts| &mut counts.deauthorizations,
+            _ => return false
+        };
+
+        *count(self.counts.lock().unwrap().entry(game.to_string()).or_default()) += 1;
+        true
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, GameEventCounts> {
+        self.counts.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventCounters, GameEventCounts, WebhookEvent};
+
+    #[test]
+    fn test_counters() {
+        let counters = EventCounters::default();
+        let event = |json: &str| serde_json::from_str::<WebhookEvent>(json).unwrap().event.unwrap();
+
+        assert!(counters.record("a", &event(r#"{"type": 1, "event": {"type": "APPLICATION_AUTHORIZED", "data": {"integration_type": 0, "guild": {"id": "1"}}}}"#)));
+        assert!(counters.record("a", &event(r#"{"type": 1, "event": {"type": "APPLICATION_AUTHORIZED", "data": {"integration_type": 1, "user": {"id": "2"}}}}"#)));
+        assert!(counters.record("a", &event(r#"{"type": 1, "event": {"type": "APPLICATION_DEAUTHORIZED", "data": {"user": {"id": "2"}}}}"#)));
+        assert!(!counters.record("a", &event(r#"{"type": 1, "event": {"type": "ENTITLEMENT_CREATE"}}"#)));
+        assert!(!counters.record("b", &event(r#"{"type": 1, "event": {"type": "ENTITLEMENT_CREATE"}}"#)));
+        assert!(!counters.snapshot().contains_key("b"));
+
+        assert_eq!(GameEventCounts { guild_authorizations: 1, user_authorizations: 1, deauthorizations: 1 }, counters.snapshot()["a"]);
+    }
+}